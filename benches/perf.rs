@@ -3,6 +3,8 @@ use fibheap::*;
 use rand::prelude::*;
 use std::collections::BinaryHeap;
 use std::iter::*;
+use std::sync::Mutex;
+use std::thread;
 
 fn random(n: usize) -> Vec<u32> {
     let mut rng = StdRng::seed_from_u64(314);
@@ -19,6 +21,11 @@ fn peeking(c: &mut Criterion) {
         let heap = v1::FibonacciHeap::from_iter(0..100_000u32);
         b.iter(|| black_box(heap.peek()));
     });
+
+    c.bench_function("pairing::PairingHeap::peek", |b| {
+        let heap = pairing::PairingHeap::from_iter(0..100_000u32);
+        b.iter(|| black_box(heap.peek()));
+    });
 }
 
 fn pushing(c: &mut Criterion) {
@@ -96,6 +103,30 @@ fn pushing(c: &mut Criterion) {
             BatchSize::SmallInput,
         );
     });
+
+    c.bench_function("pairing::PairingHeap::push one-el n100", |b| {
+        b.iter_batched_ref(
+            || pairing::PairingHeap::from_iter(lrg.iter().take(100).copied()),
+            |heap| heap.push(500),
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("pairing::PairingHeap::push one-el n10_000", |b| {
+        b.iter_batched_ref(
+            || pairing::PairingHeap::from_iter(lrg.iter().take(10_000).copied()),
+            |heap| heap.push(500),
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("pairing::PairingHeap::push one-el n10_000_000", |b| {
+        b.iter_batched_ref(
+            || pairing::PairingHeap::from_iter(lrg.iter().take(10_000_000).copied()),
+            |heap| heap.push(500),
+            BatchSize::SmallInput,
+        );
+    });
 }
 
 fn construction(c: &mut Criterion) {
@@ -123,6 +154,14 @@ fn construction(c: &mut Criterion) {
             ))
         })
     });
+
+    c.bench_function("pairing::PairingHeap::from_iter n10_000", |b| {
+        b.iter_with_large_drop(|| {
+            black_box(pairing::PairingHeap::from_iter(
+                lrg.iter().take(10_000).copied(),
+            ))
+        })
+    });
 }
 
 fn draining(c: &mut Criterion) {
@@ -199,6 +238,94 @@ fn draining(c: &mut Criterion) {
             BatchSize::SmallInput,
         );
     });
+
+    c.bench_function("binomial::BinomialHeap drain 1000", |b| {
+        b.iter_batched(
+            || binomial::BinomialHeap::from_iter(lrg.iter().take(1000).copied()),
+            |mut heap| loop {
+                if heap.pop().is_none() {
+                    break;
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("binomial::BinomialHeap drain 100_000", |b| {
+        b.iter_batched(
+            || binomial::BinomialHeap::from_iter(lrg.iter().copied()),
+            |mut heap| loop {
+                if heap.pop().is_none() {
+                    break;
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("pairing::PairingHeap two-pass drain 1000", |b| {
+        b.iter_batched(
+            || {
+                let mut heap = pairing::PairingHeap::with_strategy(pairing::Strategy::TwoPass);
+                heap.extend(lrg.iter().take(1000).copied());
+                heap
+            },
+            |mut heap| loop {
+                if heap.pop().is_none() {
+                    break;
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("pairing::PairingHeap two-pass drain 100_000", |b| {
+        b.iter_batched(
+            || {
+                let mut heap = pairing::PairingHeap::with_strategy(pairing::Strategy::TwoPass);
+                heap.extend(lrg.iter().copied());
+                heap
+            },
+            |mut heap| loop {
+                if heap.pop().is_none() {
+                    break;
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("pairing::PairingHeap multipass drain 1000", |b| {
+        b.iter_batched(
+            || {
+                let mut heap = pairing::PairingHeap::with_strategy(pairing::Strategy::Multipass);
+                heap.extend(lrg.iter().take(1000).copied());
+                heap
+            },
+            |mut heap| loop {
+                if heap.pop().is_none() {
+                    break;
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("pairing::PairingHeap multipass drain 100_000", |b| {
+        b.iter_batched(
+            || {
+                let mut heap = pairing::PairingHeap::with_strategy(pairing::Strategy::Multipass);
+                heap.extend(lrg.iter().copied());
+                heap
+            },
+            |mut heap| loop {
+                if heap.pop().is_none() {
+                    break;
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
 }
 
 fn use_case(c: &mut Criterion) {
@@ -261,7 +388,419 @@ fn use_case(c: &mut Criterion) {
             heap
         });
     });
+
+    c.bench_function("binomial::BinomialHeap randomops 10_000", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = binomial::BinomialHeap::new();
+            for op in &ops {
+                match op {
+                    Op::Pop => {
+                        heap.pop();
+                    }
+                    Op::Push(x) => heap.push(*x),
+                }
+            }
+
+            heap
+        });
+    });
+
+    c.bench_function("pairing::PairingHeap randomops 10_000", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = pairing::PairingHeap::new();
+            for op in &ops {
+                match op {
+                    Op::Pop => {
+                        heap.pop();
+                    }
+                    Op::Push(x) => {
+                        heap.push(*x);
+                    }
+                }
+            }
+
+            heap
+        });
+    });
+
+    // keys never below the last pop, as in Dijkstra, which the radix heap
+    // needs: each op pops, or pushes the last popped key plus some distance
+    let mut rng = StdRng::seed_from_u64(314);
+    let ops = repeat_with(|| match rng.gen_range(0..10u8) == 0 {
+        true => None,
+        false => Some(rng.gen_range(0..1000u64)),
+    })
+    .take(10_000)
+    .collect::<Vec<_>>();
+
+    c.bench_function("v2::FibonacciHeap monotone 10_000", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = v2::FibonacciHeap::new();
+            let mut last = 0;
+            for op in &ops {
+                match op {
+                    None => last = heap.pop().unwrap_or(last),
+                    Some(d) => heap.push(last + d),
+                }
+            }
+
+            heap
+        });
+    });
+
+    c.bench_function("radix::RadixHeap monotone 10_000", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = radix::RadixHeap::new();
+            let mut last = 0;
+            for op in &ops {
+                match op {
+                    None => last = heap.pop().map_or(last, |(k, ())| k),
+                    Some(d) => heap.push(last + d, ()).unwrap(),
+                }
+            }
+
+            heap
+        });
+    });
+}
+
+fn concurrent(c: &mut Criterion) {
+    // each thread pushes its share and pops about as many
+    const OPS: usize = 100_000;
+    let threads = thread::available_parallelism().map_or(4, |x| x.get());
+    let lrg = random(OPS);
+    let per = OPS / threads;
+
+    c.bench_function("Mutex<std::BinaryHeap> concurrent push-pop 100_000", |b| {
+        b.iter(|| {
+            let heap = Mutex::new(BinaryHeap::new());
+            thread::scope(|s| {
+                for xs in lrg.chunks(per) {
+                    let heap = &heap;
+                    s.spawn(move || {
+                        for x in xs {
+                            heap.lock().unwrap().push(*x);
+                            black_box(heap.lock().unwrap().pop());
+                        }
+                    });
+                }
+            });
+        });
+    });
+
+    c.bench_function("concurrent::MultiQueue concurrent push-pop 100_000", |b| {
+        b.iter(|| {
+            let heap = concurrent::MultiQueue::new();
+            thread::scope(|s| {
+                for xs in lrg.chunks(per) {
+                    let heap = &heap;
+                    s.spawn(move || {
+                        for x in xs {
+                            heap.push(*x);
+                            black_box(heap.pop());
+                        }
+                    });
+                }
+            });
+        });
+    });
+}
+
+// merges many small worklists into one, then drains part of it
+fn worklists(c: &mut Criterion) {
+    let lrg = random(10_000);
+
+    macro_rules! worklists {
+        ($name:literal, $heap:ty, $meld:expr) => {
+            c.bench_function(concat!($name, " meld 1000x10"), |b| {
+                b.iter_batched(
+                    || {
+                        lrg.chunks(10)
+                            .map(|c| <$heap>::from_iter(c.iter().copied()))
+                            .collect::<Vec<_>>()
+                    },
+                    |lists| {
+                        let mut all = <$heap>::new();
+                        for list in lists {
+                            $meld(&mut all, list);
+                        }
+                        for _ in 0..1000 {
+                            black_box(all.pop());
+                        }
+                        all
+                    },
+                    BatchSize::SmallInput,
+                );
+            });
+        };
+    }
+
+    worklists!(
+        "binomial::BinomialHeap",
+        binomial::BinomialHeap<u32>,
+        |a: &mut binomial::BinomialHeap<_>, mut b| a.append(&mut b)
+    );
+    worklists!(
+        "leftist::LeftistHeap",
+        leftist::LeftistHeap<u32>,
+        leftist::LeftistHeap::meld
+    );
+    worklists!(
+        "pairing::PairingHeap",
+        pairing::PairingHeap<u32>,
+        pairing::PairingHeap::meld
+    );
+    worklists!("skew::SkewHeap", skew::SkewHeap<u32>, skew::SkewHeap::meld);
+    worklists!(
+        "v2::FibonacciHeap",
+        v2::FibonacciHeap<u32>,
+        |a: &mut v2::FibonacciHeap<_>, mut b| a.append(&mut b)
+    );
+}
+
+fn selection(c: &mut Criterion) {
+    let lrg = random(1_000_000);
+
+    c.bench_function("slice::select_nth_unstable 100_000", |b| {
+        b.iter_batched(
+            || lrg[..100_000].to_vec(),
+            |mut xs| *xs.select_nth_unstable(50_000).1,
+            BatchSize::LargeInput,
+        );
+    });
+
+    c.bench_function("soft::select_kth 100_000", |b| {
+        b.iter_batched(
+            || lrg[..100_000].to_vec(),
+            |xs| soft::select_kth(xs, 50_000),
+            BatchSize::LargeInput,
+        );
+    });
+
+    c.bench_function("soft::select_kth 1_000_000", |b| {
+        b.iter_batched(
+            || lrg.clone(),
+            |xs| soft::select_kth(xs, 500_000),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+// the randomops workload at growing sizes, to see where v2's lazy pushes
+// beat the flat d-ary heap's cache locality
+fn sizes(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(314);
+    // true to pop
+    let ops = repeat_with(|| (rng.gen_range(0..10u8) == 0, rng.gen::<u32>()))
+        .take(1_000_000)
+        .collect::<Vec<_>>();
+
+    macro_rules! sizes {
+        ($name:literal, $heap:expr, $($n:literal)*) => {$(
+            c.bench_function(concat!($name, " randomops ", stringify!($n)), |b| {
+                b.iter_with_large_drop(|| {
+                    let mut heap = $heap;
+                    for &(pop, x) in &ops[..$n] {
+                        if pop {
+                            heap.pop();
+                        } else {
+                            heap.push(x);
+                        }
+                    }
+
+                    heap
+                });
+            });
+        )*};
+    }
+
+    sizes!("dary::DaryHeap<2>", dary::DaryHeap::<_, 2>::new(), 1_000 100_000 1_000_000);
+    sizes!("dary::DaryHeap<4>", dary::DaryHeap::<_, 4>::new(), 1_000 100_000 1_000_000);
+    sizes!("dary::DaryHeap<8>", dary::DaryHeap::<_, 8>::new(), 1_000 100_000 1_000_000);
+    sizes!("v2::FibonacciHeap", v2::FibonacciHeap::new(), 1_000 100_000 1_000_000);
+}
+
+// single-source shortest paths over a random graph, where heaps without
+// decrease_key push duplicates and skip the stale entries
+fn dijkstra(c: &mut Criterion) {
+    const NODES: usize = 10_000;
+    const EDGES: usize = 100_000;
+
+    let mut rng = StdRng::seed_from_u64(314);
+    let mut graph = vec![Vec::new(); NODES];
+    for _ in 0..EDGES {
+        let (u, v) = (rng.gen_range(0..NODES), rng.gen_range(0..NODES));
+        graph[u].push((v, rng.gen_range(1..1000u32)));
+    }
+    let graph = &graph;
+
+    fn lazy<H>(
+        graph: &[Vec<(usize, u32)>],
+        mut heap: H,
+        push: fn(&mut H, (u32, usize)),
+        pop: fn(&mut H) -> Option<(u32, usize)>,
+    ) -> Vec<u32> {
+        let mut dist = vec![u32::MAX; graph.len()];
+        dist[0] = 0;
+        push(&mut heap, (0, 0));
+
+        while let Some((d, u)) = pop(&mut heap) {
+            if d > dist[u] {
+                continue;
+            }
+            for &(v, w) in &graph[u] {
+                if d + w < dist[v] {
+                    dist[v] = d + w;
+                    push(&mut heap, (d + w, v));
+                }
+            }
+        }
+
+        dist
+    }
+
+    c.bench_function("std::BinaryHeap dijkstra 10_000", |b| {
+        use std::cmp::Reverse;
+        b.iter(|| {
+            lazy(
+                graph,
+                BinaryHeap::new(),
+                |h, x| h.push(Reverse(x)),
+                |h| h.pop().map(|Reverse(x)| x),
+            )
+        });
+    });
+
+    c.bench_function("v2::FibonacciHeap dijkstra 10_000", |b| {
+        b.iter(|| {
+            lazy(
+                graph,
+                v2::FibonacciHeap::new(),
+                |h, x| h.push(x),
+                |h| h.pop(),
+            )
+        });
+    });
+
+    macro_rules! decrease_key {
+        ($name:literal, $heap:expr) => {
+            c.bench_function(concat!($name, " dijkstra 10_000"), |b| {
+                b.iter(|| {
+                    let mut heap = $heap;
+                    let mut handles = vec![None; NODES];
+                    let mut dist = vec![u32::MAX; NODES];
+                    dist[0] = 0;
+                    heap.push((0, 0));
+
+                    while let Some((d, u)) = heap.pop() {
+                        for &(v, w) in &graph[u] {
+                            if d + w < dist[v] {
+                                dist[v] = d + w;
+                                match handles[v] {
+                                    Some(h) => heap.decrease_key(h, (d + w, v)),
+                                    None => handles[v] = Some(heap.push((d + w, v))),
+                                }
+                            }
+                        }
+                    }
+
+                    dist
+                });
+            });
+        };
+    }
+
+    decrease_key!("dary::DaryHeap<4>", dary::DaryHeap::<_, 4>::new());
+    decrease_key!("hollow::HollowHeap", hollow::HollowHeap::new());
+    decrease_key!("pairing::PairingHeap", pairing::PairingHeap::new());
+    decrease_key!(
+        "rank_pairing::RankPairingHeap",
+        rank_pairing::RankPairingHeap::new()
+    );
+    decrease_key!(
+        "strict::StrictFibonacciHeap",
+        strict::StrictFibonacciHeap::new()
+    );
+}
+
+// replays a recorded workload, see `fibheap::trace`, if FIBHEAP_TRACE is set
+fn recorded(c: &mut Criterion) {
+    use fibheap::trace::{Op, TraceReader};
+    use std::cmp::Reverse;
+
+    let Ok(path) = std::env::var("FIBHEAP_TRACE") else {
+        return;
+    };
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let ops = TraceReader::<u64, _>::new(file)
+        .unwrap()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap();
+
+    c.bench_function("std::BinaryHeap trace", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = BinaryHeap::new();
+            for op in &ops {
+                match op {
+                    Op::Push(x) => heap.push(Reverse(*x)),
+                    Op::Pop => {
+                        black_box(heap.pop());
+                    }
+                    Op::Meld(xs) => heap.extend(xs.iter().copied().map(Reverse)),
+                }
+            }
+
+            heap
+        });
+    });
+
+    c.bench_function("v1::FibonacciHeap trace", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = v1::FibonacciHeap::new();
+            for op in &ops {
+                match op {
+                    Op::Push(x) => heap.push(*x),
+                    Op::Pop => {
+                        black_box(heap.pop());
+                    }
+                    Op::Meld(xs) => heap.extend(xs.iter().copied()),
+                }
+            }
+
+            heap
+        });
+    });
+
+    c.bench_function("v2::FibonacciHeap trace", |b| {
+        b.iter_with_large_drop(|| {
+            let mut heap = v2::FibonacciHeap::new();
+            for op in &ops {
+                match op {
+                    Op::Push(x) => heap.push(*x),
+                    Op::Pop => {
+                        black_box(heap.pop());
+                    }
+                    Op::Meld(xs) => heap.append(&mut xs.iter().copied().collect()),
+                }
+            }
+
+            heap
+        });
+    });
 }
 
-criterion_group!(benches, peeking, pushing, construction, draining, use_case);
+criterion_group!(
+    benches,
+    peeking,
+    pushing,
+    construction,
+    draining,
+    use_case,
+    concurrent,
+    worklists,
+    selection,
+    sizes,
+    dijkstra,
+    recorded
+);
 criterion_main!(benches);
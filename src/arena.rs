@@ -0,0 +1,353 @@
+//! Node storage for the addressable heaps, with generational handles.
+use std::ops::{Index, IndexMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Refers to an element pushed onto an addressable heap.
+///
+/// A handle goes stale once its element is popped, or once a meld moves its
+/// element into another heap. A handle is also stale to any heap but the one
+/// which returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    pub(crate) index: usize,
+    gen: u32,
+    arena: u64,
+}
+
+pub(crate) struct Arena<N> {
+    slots: Vec<Slot<N>>,
+    free: Vec<usize>,
+    // tells this arena's handles from those of any other
+    id: u64,
+}
+
+struct Slot<N> {
+    gen: u32,
+    node: Option<N>,
+}
+
+// shared by both kinds of arena, as they hand out the same handles
+fn next_id() -> u64 {
+    static IDS: AtomicU64 = AtomicU64::new(0);
+    IDS.fetch_add(1, Ordering::Relaxed)
+}
+
+impl<N> Arena<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            id: next_id(),
+        }
+    }
+
+    /// The number of slots, occupied or free, so the index the next
+    /// [`Arena::append`] will start from.
+    pub(crate) fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.slots
+            .reserve(additional.saturating_sub(self.free.len()));
+    }
+
+    pub(crate) fn insert(&mut self, node: N) -> Handle {
+        let index = match self.free.pop() {
+            Some(i) => {
+                self.slots[i].node = Some(node);
+                i
+            }
+            None => {
+                self.slots.push(Slot {
+                    gen: 0,
+                    node: Some(node),
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        Handle {
+            index,
+            gen: self.slots[index].gen,
+            arena: self.id,
+        }
+    }
+
+    /// Removes the node at `index`, staling any handles to it.
+    pub(crate) fn remove(&mut self, index: usize) -> N {
+        let slot = &mut self.slots[index];
+        let node = slot.node.take().expect("slot is occupied");
+        slot.gen = slot.gen.wrapping_add(1);
+        self.free.push(index);
+        node
+    }
+
+    pub(crate) fn get(&self, handle: Handle) -> Option<&N> {
+        if handle.arena != self.id {
+            return None;
+        }
+        let slot = self.slots.get(handle.index)?;
+        match &slot.node {
+            Some(node) if slot.gen == handle.gen => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Moves the nodes of `other` to the end of this arena, returning the
+    /// offset added to their indices. `shift` should add the offset to any
+    /// indices held by a node. Handles into `other` go stale.
+    pub(crate) fn append(&mut self, other: Self, shift: impl Fn(N, usize) -> N) -> usize {
+        let base = self.slots.len();
+
+        for (i, slot) in other.slots.into_iter().enumerate() {
+            let node = slot.node.map(|n| shift(n, base));
+            if node.is_none() {
+                self.free.push(base + i);
+            }
+            self.slots.push(Slot { gen: 0, node });
+        }
+
+        base
+    }
+}
+
+impl<N> Index<usize> for Arena<N> {
+    type Output = N;
+
+    fn index(&self, i: usize) -> &N {
+        self.slots[i].node.as_ref().expect("slot is occupied")
+    }
+}
+
+impl<N> IndexMut<usize> for Arena<N> {
+    fn index_mut(&mut self, i: usize) -> &mut N {
+        self.slots[i].node.as_mut().expect("slot is occupied")
+    }
+}
+
+/// An arena whose slots never move, so that another arena's can be taken
+/// in without copying them, and no insert has to copy the slots already
+/// there.
+///
+/// Slots come from chunks which are allocated at their full capacity and
+/// never grow, and a node's index is the address of its slot. Chunks are
+/// only freed when the arena drops, so an index made by this arena, or by
+/// one appended to it, stays good for as long as the arena lives. Any other
+/// index must not be used with it.
+pub(crate) struct StableArena<N> {
+    // the chunk new slots are taken from, with the rest hanging off it
+    chunk: Option<Box<Chunk<N>>>,
+    // free slots, linked through `next_free`
+    free: Option<usize>,
+    free_tail: Option<usize>,
+    capacity: usize,
+    id: u64,
+}
+
+struct Chunk<N> {
+    slots: Vec<StableSlot<N>>,
+    // a binary tree, so that a new chunk or another arena's chunks join in
+    // O(1)
+    older: Option<Box<Chunk<N>>>,
+    appended: Option<Box<Chunk<N>>>,
+}
+
+struct StableSlot<N> {
+    gen: u32,
+    node: Option<N>,
+    next_free: Option<usize>,
+}
+
+impl<N> StableArena<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            chunk: None,
+            free: None,
+            free_tail: None,
+            capacity: 0,
+            id: next_id(),
+        }
+    }
+
+    /// Makes room for `additional` more nodes, beyond any free slots.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        if self.spare() < additional {
+            self.grow(additional);
+        }
+    }
+
+    pub(crate) fn insert(&mut self, node: N) -> Handle {
+        let index = match self.free {
+            Some(i) => {
+                let slot = self.slot_mut(i);
+                slot.node = Some(node);
+                let next = slot.next_free.take();
+                self.free = next;
+                if next.is_none() {
+                    self.free_tail = None;
+                }
+                i
+            }
+            None => {
+                if self.spare() == 0 {
+                    // chunks double, so there are O(log n) of them
+                    self.grow(self.capacity.max(16));
+                }
+                let slots = &mut self.chunk.as_mut().expect("grown").slots;
+                // within capacity, so the chunk is not reallocated
+                slots.push(StableSlot {
+                    gen: 0,
+                    node: Some(node),
+                    next_free: None,
+                });
+                let slot: *mut StableSlot<N> = slots.last_mut().expect("pushed");
+                slot.expose_provenance()
+            }
+        };
+
+        Handle {
+            index,
+            gen: self.slot(index).gen,
+            arena: self.id,
+        }
+    }
+
+    /// Removes the node at `index`, staling any handles to it.
+    pub(crate) fn remove(&mut self, index: usize) -> N {
+        let free = self.free;
+        let slot = self.slot_mut(index);
+        let node = slot.node.take().expect("slot is occupied");
+        slot.gen = slot.gen.wrapping_add(1);
+        slot.next_free = free;
+        self.free = Some(index);
+        if free.is_none() {
+            self.free_tail = Some(index);
+        }
+        node
+    }
+
+    pub(crate) fn get(&self, handle: Handle) -> Option<&N> {
+        // only this arena makes handles with its id, so the index is one of
+        // its slots
+        if handle.arena != self.id {
+            return None;
+        }
+        let slot = self.slot(handle.index);
+        match &slot.node {
+            Some(node) if slot.gen == handle.gen => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Takes in the nodes of `other`, in `O(1)`. Their indices stay the
+    /// same, and handles into `other` go stale.
+    pub(crate) fn append(&mut self, mut other: Self) {
+        let Some(theirs) = other.chunk.take() else {
+            return;
+        };
+        self.chunk = Some(match self.chunk.take() {
+            // a new node for the tree, which new slots still come from
+            Some(mut ours) => Box::new(Chunk {
+                slots: std::mem::take(&mut ours.slots),
+                older: Some(ours),
+                appended: Some(theirs),
+            }),
+            None => theirs,
+        });
+        self.capacity += other.capacity;
+
+        if let Some(head) = other.free.take() {
+            match self.free_tail {
+                Some(tail) => self.slot_mut(tail).next_free = Some(head),
+                None => self.free = Some(head),
+            }
+            self.free_tail = other.free_tail.take();
+        }
+    }
+
+    /// The slots left in the current chunk.
+    fn spare(&self) -> usize {
+        self.chunk
+            .as_ref()
+            .map_or(0, |c| c.slots.capacity() - c.slots.len())
+    }
+
+    /// Starts a new chunk of at least `capacity` slots. What is left of the
+    /// current one goes unused.
+    fn grow(&mut self, capacity: usize) {
+        let slots = Vec::with_capacity(capacity);
+        self.capacity += slots.capacity();
+        self.chunk = Some(Box::new(Chunk {
+            slots,
+            older: self.chunk.take(),
+            appended: None,
+        }));
+    }
+
+    fn slot(&self, i: usize) -> &StableSlot<N> {
+        // SAFETY: `i` is the address of a slot in a chunk of this arena,
+        // which neither moves nor frees it while the arena lives
+        unsafe { &*std::ptr::with_exposed_provenance(i) }
+    }
+
+    fn slot_mut(&mut self, i: usize) -> &mut StableSlot<N> {
+        // SAFETY: as in `slot`, and `&mut self` keeps it the only reference
+        unsafe { &mut *std::ptr::with_exposed_provenance_mut(i) }
+    }
+}
+
+impl<N> Drop for StableArena<N> {
+    fn drop(&mut self) {
+        // a long chain of melds makes a deep tree, so no recursion
+        let mut stack = Vec::from_iter(self.chunk.take());
+        while let Some(mut chunk) = stack.pop() {
+            stack.extend(chunk.older.take());
+            stack.extend(chunk.appended.take());
+        }
+    }
+}
+
+impl<N> Index<usize> for StableArena<N> {
+    type Output = N;
+
+    fn index(&self, i: usize) -> &N {
+        self.slot(i).node.as_ref().expect("slot is occupied")
+    }
+}
+
+impl<N> IndexMut<usize> for StableArena<N> {
+    fn index_mut(&mut self, i: usize) -> &mut N {
+        self.slot_mut(i).node.as_mut().expect("slot is occupied")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_append_keeps_indices_and_free_slots() {
+        let mut a = StableArena::new();
+        let mut b = StableArena::new();
+        let xs = (0..40).map(|i| a.insert(i)).collect::<Vec<_>>();
+        let ys = (0..40).map(|i| b.insert(100 + i)).collect::<Vec<_>>();
+        a.remove(xs[3].index);
+        b.remove(ys[5].index);
+        b.remove(ys[7].index);
+
+        a.append(b);
+        assert_eq!(a.get(xs[4]), Some(&4));
+        assert_eq!(a.get(xs[3]), None);
+        assert_eq!(a.get(ys[4]), None);
+        assert_eq!(a[ys[4].index], 104);
+
+        // the free slots of both are used before any new ones
+        let mut reused = (0..3).map(|i| a.insert(200 + i).index).collect::<Vec<_>>();
+        let mut freed = vec![xs[3].index, ys[5].index, ys[7].index];
+        reused.sort();
+        freed.sort();
+        assert_eq!(reused, freed);
+        assert!(!freed.contains(&a.insert(300).index));
+    }
+}
@@ -0,0 +1,314 @@
+//! An executor-agnostic asynchronous priority queue.
+//!
+//! [`AsyncPriorityQueue::pop`] resolves to the _minimum_ element, waiting for a
+//! push if the queue is empty. Only [`std::task::Waker`] is used, so the queue
+//! works with any executor (or none, see the `block_on` used in the tests).
+use crate::v2::FibonacciHeap;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+pub struct AsyncPriorityQueue<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    heap: FibonacciHeap<T>,
+    // waiting pops, keyed in registration order so they are woken first-come
+    // first-served
+    waiters: BTreeMap<u64, Waker>,
+    next_key: u64,
+}
+
+impl<T> State<T> {
+    /// Takes the longest waiting pop's waker. Wake it only once the lock is
+    /// released, as an executor may poll the pop inline.
+    fn take_waiter(&mut self) -> Option<Waker> {
+        self.waiters.pop_first().map(|(_, w)| w)
+    }
+}
+
+impl<T: Ord> AsyncPriorityQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                heap: FibonacciHeap::new(),
+                waiters: BTreeMap::new(),
+                next_key: 0,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // the heap is never left half-modified, so poisoning can be ignored
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push an element, waking one pending [`pop`](Self::pop) if any.
+    pub fn push(&self, item: T) {
+        let mut state = self.lock();
+        state.heap.push(item);
+        let waiter = state.take_waiter();
+        drop(state);
+
+        if let Some(w) = waiter {
+            w.wake();
+        }
+    }
+
+    /// Pop the minimum element if one is available, without waiting.
+    pub fn try_pop(&self) -> Option<T> {
+        self.lock().heap.pop()
+    }
+
+    /// Pop the minimum element, waiting until one is pushed if empty.
+    ///
+    /// Dropping the future before it completes is fine, a wake-up it may have
+    /// received is passed along to the next waiting pop.
+    pub async fn pop(&self) -> T {
+        Pop {
+            queue: self,
+            key: None,
+        }
+        .await
+    }
+}
+
+impl<T: Ord> Default for AsyncPriorityQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AsyncPriorityQueue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let q = Self::new();
+        q.lock().heap.extend(iter);
+        q
+    }
+}
+
+struct Pop<'a, T: Ord> {
+    queue: &'a AsyncPriorityQueue<T>,
+    // registration in `waiters`, if the last poll was pending
+    key: Option<u64>,
+}
+
+impl<T: Ord> Future for Pop<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let queue = self.queue;
+        let mut state = queue.lock();
+
+        if let Some(x) = state.heap.pop() {
+            if let Some(key) = self.key.take() {
+                state.waiters.remove(&key);
+            }
+            return Poll::Ready(x);
+        }
+
+        match self.key.and_then(|k| state.waiters.get_mut(&k)) {
+            // still registered, refresh the waker in place
+            Some(w) => w.clone_from(cx.waker()),
+            // first poll, or we were woken but lost the race for the element
+            None => {
+                let key = state.next_key;
+                state.next_key += 1;
+                state.waiters.insert(key, cx.waker().clone());
+                self.key = Some(key);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T: Ord> Drop for Pop<'_, T> {
+    fn drop(&mut self) {
+        let key = match self.key {
+            Some(k) => k,
+            None => return,
+        };
+
+        let mut state = self.queue.lock();
+        if state.waiters.remove(&key).is_none() && !state.heap.is_empty() {
+            // we were woken for an element we will never take, pass it on
+            let waiter = state.take_waiter();
+            drop(state);
+
+            if let Some(w) = waiter {
+                w.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+    use std::time::Duration;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = std::pin::pin!(fut);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(x) => return x,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[quickcheck]
+    fn pops_by_min(xs: Vec<u32>) {
+        let q = AsyncPriorityQueue::from_iter(xs.iter().copied());
+        let mut xs = xs;
+        xs.sort();
+
+        let got = block_on(async {
+            let mut v = Vec::new();
+            while !q.is_empty() {
+                v.push(q.pop().await);
+            }
+            v
+        });
+
+        assert_eq!(got, xs);
+    }
+
+    #[test]
+    fn pop_waits_for_push() {
+        let q = Arc::new(AsyncPriorityQueue::new());
+
+        let q_ = q.clone();
+        let h = thread::spawn(move || block_on(async { (q_.pop().await, q_.pop().await) }));
+
+        thread::sleep(Duration::from_millis(10));
+        q.push(3);
+        thread::sleep(Duration::from_millis(10));
+        q.push(1);
+
+        assert_eq!(h.join().unwrap(), (3, 1));
+    }
+
+    #[test]
+    fn many_consumers() {
+        let q = Arc::new(AsyncPriorityQueue::new());
+
+        let handles = (0..4)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || block_on(q.pop()))
+            })
+            .collect::<Vec<_>>();
+
+        for x in 0..4 {
+            q.push(x);
+        }
+
+        let mut got = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        got.sort();
+        assert_eq!(got, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn dropped_pop_passes_on_wakeup() {
+        let q = AsyncPriorityQueue::new();
+
+        let wa = Arc::new(CountWaker::default());
+        let wb = Arc::new(CountWaker::default());
+        let waker_a = Waker::from(wa.clone());
+        let waker_b = Waker::from(wb.clone());
+
+        let mut a = Box::pin(q.pop());
+        let mut b = Box::pin(q.pop());
+        assert!(a
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker_a))
+            .is_pending());
+        assert!(b
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker_b))
+            .is_pending());
+
+        // a is first in line
+        q.push(1);
+        assert_eq!(wa.0.load(Ordering::SeqCst), 1);
+        assert_eq!(wb.0.load(Ordering::SeqCst), 0);
+
+        // but it gives up, so b gets woken instead
+        drop(a);
+        assert_eq!(wb.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            b.as_mut().poll(&mut Context::from_waker(&waker_b)),
+            Poll::Ready(1)
+        );
+    }
+
+    // a waker which polls inline, as some executors do, and so must find
+    // the queue unlocked
+    struct InlineWaker(Arc<AsyncPriorityQueue<u32>>, AtomicUsize);
+
+    impl Wake for InlineWaker {
+        fn wake(self: Arc<Self>) {
+            assert!(self.0.state.try_lock().is_ok(), "woken under the lock");
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wakes_outside_the_lock() {
+        let q = Arc::new(AsyncPriorityQueue::new());
+        let w = Arc::new(InlineWaker(q.clone(), AtomicUsize::new(0)));
+        let waker = Waker::from(w.clone());
+
+        let mut a = Box::pin(q.pop());
+        let mut b = Box::pin(q.pop());
+        let cx = &mut Context::from_waker(&waker);
+        assert!(a.as_mut().poll(cx).is_pending());
+        assert!(b.as_mut().poll(cx).is_pending());
+
+        // from push, then from a dropped pop passing its wake-up on
+        q.push(1);
+        drop(a);
+        assert_eq!(w.1.load(Ordering::SeqCst), 2);
+        assert_eq!(b.as_mut().poll(cx), Poll::Ready(1));
+    }
+}
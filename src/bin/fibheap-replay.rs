@@ -0,0 +1,150 @@
+//! Replays a recorded trace of `u64` operations against each heap.
+//!
+//! Usage: `fibheap-replay <trace> [runs]`
+//!
+//! Every backend must pop the same sequence, otherwise the first difference
+//! is reported and the exit status is non-zero. The fastest of `runs` timings
+//! (default 5) is reported for each backend.
+use fibheap::trace::{Op, TraceReader};
+use fibheap::{v1, v2};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// The operations each backend needs to replay a trace.
+trait Backend: Default {
+    const NAME: &'static str;
+    fn push(&mut self, x: u64);
+    fn pop(&mut self) -> Option<u64>;
+    fn meld(&mut self, xs: &[u64]);
+}
+
+impl Backend for BinaryHeap<Reverse<u64>> {
+    const NAME: &'static str = "std::BinaryHeap";
+
+    fn push(&mut self, x: u64) {
+        BinaryHeap::push(self, Reverse(x));
+    }
+
+    fn pop(&mut self) -> Option<u64> {
+        BinaryHeap::pop(self).map(|x| x.0)
+    }
+
+    fn meld(&mut self, xs: &[u64]) {
+        let mut other = xs.iter().copied().map(Reverse).collect();
+        self.append(&mut other);
+    }
+}
+
+impl Backend for v1::FibonacciHeap<u64> {
+    const NAME: &'static str = "v1::FibonacciHeap";
+
+    fn push(&mut self, x: u64) {
+        v1::FibonacciHeap::push(self, x);
+    }
+
+    fn pop(&mut self) -> Option<u64> {
+        v1::FibonacciHeap::pop(self)
+    }
+
+    fn meld(&mut self, xs: &[u64]) {
+        // v1 cannot meld, so fall back to pushing
+        self.extend(xs.iter().copied());
+    }
+}
+
+impl Backend for v2::FibonacciHeap<u64> {
+    const NAME: &'static str = "v2::FibonacciHeap";
+
+    fn push(&mut self, x: u64) {
+        v2::FibonacciHeap::push(self, x);
+    }
+
+    fn pop(&mut self) -> Option<u64> {
+        v2::FibonacciHeap::pop(self)
+    }
+
+    fn meld(&mut self, xs: &[u64]) {
+        let mut other = xs.iter().copied().collect();
+        self.append(&mut other);
+    }
+}
+
+/// Replays `ops`, returning the popped values and the fastest run.
+fn replay<H: Backend>(ops: &[Op<u64>], runs: usize) -> (Vec<Option<u64>>, Duration) {
+    let mut best = Duration::MAX;
+    let mut out = Vec::new();
+
+    for _ in 0..runs.max(1) {
+        out.clear();
+        let mut heap = H::default();
+
+        let start = Instant::now();
+        for op in ops {
+            match op {
+                Op::Push(x) => heap.push(*x),
+                Op::Pop => out.push(heap.pop()),
+                Op::Meld(xs) => heap.meld(xs),
+            }
+        }
+        best = best.min(start.elapsed());
+    }
+
+    (out, best)
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let (Some(path), runs) = (args.next(), args.next()) else {
+        eprintln!("usage: fibheap-replay <trace> [runs]");
+        return ExitCode::FAILURE;
+    };
+    let Ok(runs) = runs.map_or(Ok(5), |r| r.parse()) else {
+        eprintln!("runs must be a number");
+        return ExitCode::FAILURE;
+    };
+
+    let ops = match read(&path) {
+        Ok(ops) => ops,
+        Err(e) => {
+            eprintln!("failed to read {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{} operations, best of {runs} runs", ops.len());
+
+    let results = [
+        replay::<BinaryHeap<Reverse<u64>>>(&ops, runs),
+        replay::<v1::FibonacciHeap<u64>>(&ops, runs),
+        replay::<v2::FibonacciHeap<u64>>(&ops, runs),
+    ];
+    let names = [
+        <BinaryHeap<Reverse<u64>>>::NAME,
+        v1::FibonacciHeap::NAME,
+        v2::FibonacciHeap::NAME,
+    ];
+
+    for (name, (_, time)) in names.iter().zip(&results) {
+        println!("{name:<20} {time:>12.3?}");
+    }
+
+    let (expected, _) = &results[0];
+    for (name, (out, _)) in names.iter().zip(&results).skip(1) {
+        if let Some(i) = (0..expected.len()).find(|&i| out[i] != expected[i]) {
+            eprintln!(
+                "{name} diverged at pop {i}: {:?} but {} popped {:?}",
+                out[i], names[0], expected[i]
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn read(path: &str) -> io::Result<Vec<Op<u64>>> {
+    TraceReader::new(BufReader::new(File::open(path)?))?.collect()
+}
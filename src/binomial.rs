@@ -0,0 +1,236 @@
+//! A binomial heap, linking eagerly so that no operation is amortised.
+//!
+//! The forest holds at most one tree of each degree, like the bits of a binary
+//! counter, and a tree of degree `d` holds exactly `2^d` nodes. Pushing and
+//! melding link trees of equal degree straight away, with carries, so every
+//! operation but [`BinomialHeap::peek`] is `O(log n)` in the worst case, and
+//! there is never a long root list left for [`BinomialHeap::pop`] to clean up.
+//!
+//! The trees, and linking them, are shared with [`v2`](crate::v2).
+use crate::v2::{Link, Tree};
+
+pub struct BinomialHeap<T> {
+    // trees[d] is the tree of degree d, if there is one
+    trees: Vec<Option<Tree<T>>>,
+    // the degree of the tree with the minimum root
+    min: Option<usize>,
+    len: usize,
+}
+
+impl<T: Ord> BinomialHeap<T> {
+    pub fn new() -> Self {
+        Self {
+            trees: Vec::new(),
+            min: None,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        let tree = self.trees[self.min?].as_ref();
+        tree.map(|t| &t.node)
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.len += 1;
+        self.carry(Tree::new(item));
+        self.find_min();
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let Tree { node, children } = self.trees[self.min?].take()?;
+        self.len -= 1;
+
+        // child d has degree d, so they are merged in like a second forest
+        self.merge(children.into_iter().map(Some).collect());
+
+        // drop empty slots at the top, so the forest stays O(log n) wide
+        while let Some(None) = self.trees.last() {
+            self.trees.pop();
+        }
+
+        self.find_min();
+        Some(node)
+    }
+
+    /// Moves all the elements of `other` into `self`, leaving `other` empty.
+    ///
+    /// This adds the two forests like binary numbers, costing `O(log n)`.
+    pub fn append(&mut self, other: &mut Self) {
+        self.len += std::mem::take(&mut other.len);
+        other.min = None;
+
+        self.merge(std::mem::take(&mut other.trees));
+        self.find_min();
+    }
+
+    /// Adds `tree` into the slot for its degree, linking and carrying upwards
+    /// while the slot is occupied.
+    fn carry(&mut self, mut tree: Tree<T>) {
+        loop {
+            let d = tree.degree();
+            if self.trees.len() <= d {
+                self.trees.resize_with(d + 1, || None);
+            }
+
+            tree = match self.trees[d].take() {
+                None => {
+                    self.trees[d] = Some(tree);
+                    return;
+                }
+                Some(other) => link(other, tree),
+            };
+        }
+    }
+
+    /// Adds a forest of trees indexed by degree, in a single pass with at
+    /// most one carry.
+    fn merge(&mut self, other: Vec<Option<Tree<T>>>) {
+        let mut other = other.into_iter();
+        let mut carry = None;
+
+        for d in 0.. {
+            let b = other.next();
+            if d >= self.trees.len() {
+                if b.is_none() && carry.is_none() {
+                    break;
+                }
+                self.trees.push(None);
+            }
+
+            // add up to three trees of degree d
+            let a = self.trees[d].take();
+            (self.trees[d], carry) = match (a, b.flatten(), carry) {
+                (Some(x), Some(y), z) => (z, Some(link(x, y))),
+                (Some(x), None, Some(y)) | (None, Some(x), Some(y)) => (None, Some(link(x, y))),
+                (x, None, None) | (None, x, None) | (None, None, x) => (x, None),
+            };
+        }
+    }
+
+    fn find_min(&mut self) {
+        self.min = self
+            .trees
+            .iter()
+            .enumerate()
+            .filter_map(|(d, t)| Some((d, t.as_ref()?.root())))
+            .min_by_key(|(_, x)| *x)
+            .map(|(d, _)| d);
+    }
+}
+
+/// Links two trees of equal degree, the lesser root becoming the parent.
+fn link<T: Ord>(a: Tree<T>, b: Tree<T>) -> Tree<T> {
+    if a.root() <= b.root() {
+        a.link(b)
+    } else {
+        b.link(a)
+    }
+}
+
+impl<T: Ord> Default for BinomialHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for BinomialHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Ord> Extend<T> for BinomialHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    fn size<T>(tree: &Tree<T>) -> usize {
+        1 + tree.children.iter().map(size).sum::<usize>()
+    }
+
+    // each slot holds a heap-ordered binomial tree of its degree, and the
+    // occupied slots spell out the length in binary
+    fn verify(heap: &BinomialHeap<u32>) {
+        fn ordered(tree: &Tree<u32>) {
+            for (d, child) in tree.children.iter().enumerate() {
+                assert!(tree.node <= child.node);
+                assert_eq!(child.degree(), d);
+                ordered(child);
+            }
+        }
+
+        let mut len = 0;
+        for (d, tree) in heap.trees.iter().enumerate() {
+            if let Some(tree) = tree {
+                assert_eq!(tree.degree(), d);
+                assert_eq!(size(tree), 1 << d);
+                ordered(tree);
+                len |= 1 << d;
+            }
+        }
+        assert_eq!(len, heap.len());
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let mut heap = BinomialHeap::new();
+        for (i, x) in xs.iter().enumerate() {
+            heap.push(*x);
+            verify(&heap);
+            if i % 4 == 0 {
+                heap.pop();
+                verify(&heap);
+            }
+        }
+
+        let mut rest = Vec::new();
+        while let Some(x) = heap.pop() {
+            verify(&heap);
+            rest.push(x);
+        }
+        assert!(rest.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[quickcheck]
+    fn append_pops_by_min(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = BinomialHeap::from_iter(xs.iter().copied());
+        let mut b = BinomialHeap::from_iter(ys.iter().copied());
+        a.append(&mut b);
+        verify(&a);
+        assert!(b.is_empty());
+        assert_eq!(b.peek(), None);
+
+        let mut exp = [xs, ys].concat();
+        exp.sort();
+        assert_eq!(std::iter::from_fn(|| a.pop()).collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    fn forest_stays_narrow() {
+        // v2 would be left with 10_000 roots here, for the next pop to consolidate
+        let mut heap = BinomialHeap::from_iter(0..10_000u32);
+        heap.pop();
+        heap.extend(0..10_000);
+        assert!(heap.trees.len() <= 15);
+        assert_eq!(heap.peek(), Some(&0));
+    }
+}
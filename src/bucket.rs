@@ -0,0 +1,456 @@
+//! Queues for integer priorities, where the priority picks a bucket rather
+//! than being compared.
+//!
+//! [`BucketQueue`] suits small priorities in a fixed `0..max`, as in Dial's
+//! algorithm. [`CalendarQueue`] suits simulation times: it spreads them over
+//! a year of days, one bucket per day, and resizes the year as the number
+//! of pending events changes.
+//!
+//! Both pop elements of equal priority in the order they were pushed.
+use crate::arena::Arena;
+
+pub use crate::arena::Handle;
+
+/// A queue for priorities in `0..max`, with a bucket per priority.
+///
+/// `push`, `peek` and `decrease_key` are `O(1)`. The least priority in use is
+/// cached, and `pop` moves it forward over the buckets emptied, a word of
+/// bits at a time. So `pop` is `O(1)` amortised when priorities only move
+/// forward, as in Dial's algorithm, where nothing is pushed below the last
+/// priority popped. Otherwise a single `pop` can scan `O(max / 64)` words.
+pub struct BucketQueue<V> {
+    nodes: Arena<Node<V>>,
+    // the first and last node of each priority
+    buckets: Vec<Option<(usize, usize)>>,
+    // a bit per priority, set while its bucket is not empty
+    occupied: Vec<u64>,
+    // the least priority in use, or max while empty
+    min: usize,
+    len: usize,
+}
+
+struct Node<V> {
+    item: (usize, V),
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl<V> BucketQueue<V> {
+    /// A queue for priorities in `0..max`.
+    pub fn new(max: usize) -> Self {
+        Self {
+            nodes: Arena::new(),
+            buckets: (0..max).map(|_| None).collect(),
+            occupied: vec![0; max.div_ceil(64)],
+            min: max,
+            len: 0,
+        }
+    }
+
+    /// The bound on priorities the queue was made with.
+    pub fn max(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The least priority and its first value.
+    pub fn peek(&self) -> Option<&(usize, V)> {
+        let (first, _) = (*self.buckets.get(self.min)?).expect("occupied");
+        Some(&self.nodes[first].item)
+    }
+
+    /// The priority and value `handle` refers to, or `None` if the handle is
+    /// stale.
+    pub fn get(&self, handle: Handle) -> Option<&(usize, V)> {
+        self.nodes.get(handle).map(|n| &n.item)
+    }
+
+    /// The value `handle` refers to, or `None` if the handle is stale.
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut V> {
+        self.nodes.get(handle)?;
+        Some(&mut self.nodes[handle.index].item.1)
+    }
+
+    /// # Panics
+    /// Panics if `priority` is not below [`Self::max`].
+    pub fn push(&mut self, priority: usize, value: V) -> Handle {
+        assert!(priority < self.max(), "priority out of range");
+        let handle = self.nodes.insert(Node {
+            item: (priority, value),
+            prev: None,
+            next: None,
+        });
+
+        self.enlist(handle.index);
+        self.len += 1;
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<(usize, V)> {
+        let p = self.min;
+        let (first, _) = (*self.buckets.get(p)?).expect("occupied");
+        self.delist(first);
+        self.len -= 1;
+
+        if self.buckets[p].is_none() {
+            self.min = self.next_from(p);
+        }
+        Some(self.nodes.remove(first).item)
+    }
+
+    /// Lowers the priority of the element `handle` refers to. It goes behind
+    /// any others already at that priority.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `priority` is greater than the
+    /// element's.
+    pub fn decrease_key(&mut self, handle: Handle, priority: usize) {
+        let node = self.nodes.get(handle).expect("handle is stale");
+        assert!(
+            priority <= node.item.0,
+            "decrease_key would increase the key"
+        );
+
+        let i = handle.index;
+        self.delist(i);
+        self.nodes[i].item.0 = priority;
+        self.enlist(i);
+    }
+
+    /// The least priority in use from `p` on, or max if there is none.
+    fn next_from(&self, p: usize) -> usize {
+        let mut w = p / 64;
+        let mut bits = match self.occupied.get(w) {
+            Some(&bits) => bits & (!0 << (p % 64)),
+            None => return self.max(),
+        };
+
+        while bits == 0 {
+            w += 1;
+            match self.occupied.get(w) {
+                Some(&next) => bits = next,
+                None => return self.max(),
+            }
+        }
+        w * 64 + bits.trailing_zeros() as usize
+    }
+
+    /// Appends node `i` to the bucket of its priority.
+    fn enlist(&mut self, i: usize) {
+        let p = self.nodes[i].item.0;
+        self.buckets[p] = Some(match self.buckets[p] {
+            Some((first, last)) => {
+                self.nodes[last].next = Some(i);
+                self.nodes[i].prev = Some(last);
+                (first, i)
+            }
+            None => (i, i),
+        });
+
+        self.occupied[p / 64] |= 1 << (p % 64);
+        // a decrease_key may have just emptied the bucket at min, but then
+        // moves its element lower still
+        self.min = self.min.min(p);
+    }
+
+    fn delist(&mut self, i: usize) {
+        let p = self.nodes[i].item.0;
+        let node = &mut self.nodes[i];
+        let (prev, next) = (node.prev.take(), node.next.take());
+        let (mut first, mut last) = self.buckets[p].expect("occupied");
+
+        match prev {
+            Some(s) => self.nodes[s].next = next,
+            None => first = next.unwrap_or(i),
+        }
+        match next {
+            Some(s) => self.nodes[s].prev = prev,
+            None => last = prev.unwrap_or(i),
+        }
+
+        if prev.is_none() && next.is_none() {
+            self.buckets[p] = None;
+            self.occupied[p / 64] &= !(1 << (p % 64));
+        } else {
+            self.buckets[p] = Some((first, last));
+        }
+    }
+}
+
+/// A calendar queue (Brown, 1988) of events at integer times, such as
+/// nanoseconds of simulated time.
+///
+/// With a day width near the typical gap between events, `push` and `pop`
+/// take expected `O(1)`. The width is estimated again from the earliest
+/// events whenever the year doubles or halves, which costs `O(n)` but only
+/// after `O(n)` operations.
+pub struct CalendarQueue<V> {
+    // each day's events, sorted with the earliest last
+    days: Vec<Vec<(u64, V)>>,
+    width: u64,
+    // the day the scan for the earliest event is at, and when it starts;
+    // no event is earlier than that
+    today: usize,
+    start: u64,
+    len: usize,
+}
+
+impl<V> CalendarQueue<V> {
+    pub fn new() -> Self {
+        Self {
+            days: (0..2).map(|_| Vec::new()).collect(),
+            width: 1,
+            today: 0,
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&(u64, V)> {
+        let (day, _) = self.find()?;
+        self.days[day].last()
+    }
+
+    pub fn push(&mut self, time: u64, value: V) {
+        if time < self.start {
+            // an event in the past moves the scan back
+            self.today = self.day(time);
+            self.start = time - time % self.width;
+        }
+
+        self.insert(time, value);
+        self.len += 1;
+        if self.len > 2 * self.days.len() {
+            self.resize(2 * self.days.len());
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(u64, V)> {
+        let (day, start) = self.find()?;
+        self.today = day;
+        self.start = start;
+
+        let event = self.days[day].pop();
+        self.len -= 1;
+        if self.len < self.days.len() / 2 && self.days.len() > 2 {
+            self.resize(self.days.len() / 2);
+        }
+        event
+    }
+
+    /// The day holding the earliest event, and when the scan reaches it.
+    fn find(&self) -> Option<(usize, u64)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        // walk the days of the year, for an event due that day rather than
+        // in a later year
+        let (mut day, mut start) = (self.today, self.start);
+        for _ in 0..self.days.len() {
+            let end = start.saturating_add(self.width);
+            if self.days[day].last().is_some_and(|&(t, _)| t < end) {
+                return Some((day, start));
+            }
+            day = (day + 1) % self.days.len();
+            start = end;
+        }
+
+        // a sparse year, so look for the earliest directly
+        let t = self
+            .days
+            .iter()
+            .filter_map(|d| d.last())
+            .map(|e| e.0)
+            .min()?;
+        Some((self.day(t), t - t % self.width))
+    }
+
+    fn day(&self, time: u64) -> usize {
+        (time / self.width % self.days.len() as u64) as usize
+    }
+
+    fn insert(&mut self, time: u64, value: V) {
+        let day = self.day(time);
+        let events = &mut self.days[day];
+        // after (so popped before) later events, and before earlier pushes
+        // at the same time
+        let i = events.partition_point(|e| e.0 > time);
+        events.insert(i, (time, value));
+    }
+
+    fn resize(&mut self, days: usize) {
+        let mut events = self.days.drain(..).flatten().collect::<Vec<_>>();
+        self.width = width(&mut events).unwrap_or(self.width);
+        self.days = (0..days).map(|_| Vec::new()).collect();
+
+        // start the scan again from the earliest event
+        let t = events.iter().map(|e| e.0).min().unwrap_or(self.start);
+        self.today = self.day(t);
+        self.start = t - t % self.width;
+
+        // reinserting in order keeps equal times in push order
+        events.reverse();
+        for (t, v) in events {
+            self.insert(t, v);
+        }
+    }
+}
+
+/// Three times the typical gap between the earliest events, leaving out
+/// the gaps more than twice the average.
+fn width<V>(events: &mut [(u64, V)]) -> Option<u64> {
+    const SAMPLE: usize = 25;
+
+    let mut times = events.iter().map(|e| e.0).collect::<Vec<_>>();
+    if times.len() < 2 {
+        return None;
+    }
+    if times.len() > SAMPLE {
+        times.select_nth_unstable(SAMPLE - 1);
+        times.truncate(SAMPLE);
+    }
+    times.sort_unstable();
+
+    let gaps = times.windows(2).map(|w| (w[1] - w[0]) as f64);
+    let avg = gaps.clone().sum::<f64>() / (times.len() - 1) as f64;
+    let (sum, n) = gaps
+        .filter(|&g| g <= 2.0 * avg)
+        .fold((0.0, 0), |(sum, n), g| (sum + g, n + 1));
+    Some(((3.0 * sum / n as f64) as u64).max(1))
+}
+
+impl<V> Default for CalendarQueue<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use rand::prelude::*;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    #[quickcheck]
+    fn bucket_matches_model(ops: Vec<(u8, u8, usize)>) {
+        let mut queue = BucketQueue::new(200);
+        // live handles, with their current priorities
+        let mut live: Vec<(Handle, usize)> = Vec::new();
+
+        for &(op, p, i) in &ops {
+            let p = p as usize % 200;
+            match op % 4 {
+                0 if !live.is_empty() => {
+                    let n = live.len();
+                    let (h, q) = &mut live[i % n];
+                    *q = p.min(*q);
+                    queue.decrease_key(*h, *q);
+                    assert_eq!(queue.get(*h).map(|x| x.0), Some(*q));
+                }
+                1 => {
+                    let min = live.iter().map(|&(_, q)| q).min();
+                    assert_eq!(queue.peek().map(|x| x.0), min);
+
+                    let popped = queue.pop().map(|x| x.0);
+                    assert_eq!(popped, min);
+                    if popped.is_some() {
+                        let j = live.iter().position(|(h, _)| queue.get(*h).is_none());
+                        live.remove(j.unwrap());
+                    }
+                }
+                _ => live.push((queue.push(p, ()), p)),
+            }
+            assert_eq!(queue.len(), live.len());
+            let min = live.iter().map(|&(_, q)| q).min();
+            assert_eq!(queue.peek().map(|x| x.0), min);
+        }
+    }
+
+    #[test]
+    fn bucket_keeps_push_order() {
+        let mut queue = BucketQueue::new(10);
+        queue.push(3, 'a');
+        let b = queue.push(5, 'b');
+        queue.push(3, 'c');
+        queue.push(0, 'd');
+        queue.decrease_key(b, 3);
+        *queue.get_mut(b).unwrap() = 'B';
+
+        let popped = std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>();
+        assert_eq!(popped, [(0, 'd'), (3, 'a'), (3, 'c'), (3, 'B')]);
+    }
+
+    #[test]
+    #[should_panic = "priority out of range"]
+    fn bucket_rejects_large_priorities() {
+        BucketQueue::new(64).push(64, ());
+    }
+
+    #[quickcheck]
+    fn calendar_matches_model(ops: Vec<(bool, u32)>, scale: u8) {
+        let mut queue = CalendarQueue::new();
+        let mut model = BinaryHeap::new();
+
+        for (seq, &(pop, t)) in ops.iter().enumerate() {
+            if pop {
+                let popped = queue.pop();
+                assert_eq!(popped, model.pop().map(|Reverse(x)| x));
+            } else {
+                // spread the times widely, or bunch them up
+                let t = (t as u64) << (scale % 40) >> 20;
+                queue.push(t, seq);
+                model.push(Reverse((t, seq)));
+            }
+
+            assert_eq!(queue.len(), model.len());
+            assert_eq!(queue.peek(), model.peek().map(|r| &r.0));
+        }
+    }
+
+    #[test]
+    fn calendar_resizes_with_a_simulation() {
+        // the classic hold model: pop an event and schedule another a random
+        // while after it
+        let mut rng = StdRng::seed_from_u64(314);
+        let mut queue = CalendarQueue::new();
+        let mut model = BinaryHeap::new();
+        for i in 0..1000 {
+            let t = rng.gen_range(0..1_000_000);
+            queue.push(t, i);
+            model.push(Reverse((t, i)));
+        }
+        assert!(queue.days.len() >= 500);
+
+        for i in 1000..100_000 {
+            let (now, _) = queue.pop().unwrap();
+            assert_eq!(Some(now), model.pop().map(|Reverse((t, _))| t));
+
+            let t = now + rng.gen_range(0..2_000);
+            queue.push(t, i);
+            model.push(Reverse((t, i)));
+        }
+
+        std::iter::from_fn(|| queue.pop())
+            .zip(std::iter::from_fn(|| model.pop()))
+            .for_each(|(a, Reverse(b))| assert_eq!(a, b));
+        assert!(queue.days.len() <= 4);
+    }
+}
@@ -0,0 +1,236 @@
+//! A sharded, relaxed concurrent priority queue (a _MultiQueue_).
+//!
+//! The queue is made up of `c·p` independently locked Fibonacci heaps, where
+//! `p` is the number of threads and `c` a small constant. [`MultiQueue::push`]
+//! places an element into a random shard, [`MultiQueue::pop`] samples two
+//! random shards and pops from the one with the lesser minimum. Popped
+//! elements are therefore not necessarily the global minimum, but their rank
+//! error is expected to be `O(shards)`, in exchange for contention that mostly
+//! disappears.
+use crate::v2::FibonacciHeap;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+pub struct MultiQueue<T> {
+    shards: Box<[Mutex<FibonacciHeap<T>>]>,
+}
+
+impl<T: Ord> MultiQueue<T> {
+    /// Create a queue with twice as many shards as available hardware threads.
+    pub fn new() -> Self {
+        let p = std::thread::available_parallelism().map_or(1, |x| x.get());
+        Self::with_shards(2 * p)
+    }
+
+    /// Create a queue with `shards` heaps.
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "a MultiQueue needs at least one shard");
+        Self {
+            shards: std::iter::repeat_with(Default::default)
+                .take(shards)
+                .collect(),
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The number of elements, summed over all shards.
+    ///
+    /// Under concurrent modification this is only a snapshot.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| lock(s).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| lock(s).is_empty())
+    }
+
+    /// Push an element into a random shard.
+    pub fn push(&self, item: T) {
+        let n = self.shards.len();
+        // prefer an uncontended shard, but settle for a locked one eventually
+        for _ in 0..n {
+            if let Ok(mut heap) = self.shards[random(n)].try_lock() {
+                heap.push(item);
+                return;
+            }
+        }
+
+        lock(&self.shards[random(n)]).push(item);
+    }
+
+    /// Pop a _small_ element: the lesser of two random shards' minima.
+    ///
+    /// Returns `None` only if every shard was observed empty.
+    pub fn pop(&self) -> Option<T> {
+        let n = self.shards.len();
+
+        if n > 1 {
+            let a = random(n);
+            let b = (a + 1 + random(n - 1)) % n; // distinct from a
+            let (lo, hi) = (a.min(b), a.max(b));
+
+            // lock in index order to avoid deadlocking with other pops
+            let mut x = lock(&self.shards[lo]);
+            let mut y = lock(&self.shards[hi]);
+
+            let heap = match (x.peek(), y.peek()) {
+                (Some(p), Some(q)) if q < p => &mut y,
+                (Some(_), _) => &mut x,
+                (None, Some(_)) => &mut y,
+                (None, None) => {
+                    drop((x, y));
+                    return self.pop_any();
+                }
+            };
+
+            return heap.pop();
+        }
+
+        self.pop_any()
+    }
+
+    // both samples were empty, fall back to scanning every shard so that
+    // `None` means the queue really was empty
+    fn pop_any(&self) -> Option<T> {
+        let n = self.shards.len();
+        let start = random(n);
+        (0..n).find_map(|i| lock(&self.shards[(start + i) % n]).pop())
+    }
+}
+
+impl<T: Ord> Default for MultiQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> Extend<T> for MultiQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        // exclusive access, so just deal the elements round-robin
+        let n = self.shards.len();
+        for (i, x) in iter.into_iter().enumerate() {
+            lock(&self.shards[i % n]).push(x);
+        }
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    // the heaps are never left half-modified, so poisoning can be ignored
+    m.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A fast thread-local xorshift generator, uniform enough for shard picking.
+fn random(n: usize) -> usize {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new({
+            // RandomState is seeded per thread
+            let mut h = RandomState::new().build_hasher();
+            h.write_usize(0);
+            h.finish() | 1
+        });
+    }
+
+    STATE.with(|s| {
+        let mut x = s.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        s.set(x);
+        (((x >> 32) * n as u64) >> 32) as usize
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[quickcheck]
+    fn pops_every_element(xs: Vec<u32>, shards: u8) {
+        let q = MultiQueue::with_shards(shards as usize % 8 + 1);
+        for x in &xs {
+            q.push(*x);
+        }
+        assert_eq!(q.len(), xs.len());
+
+        let mut got = std::iter::from_fn(|| q.pop()).collect::<Vec<_>>();
+        assert!(q.is_empty());
+
+        let mut xs = xs;
+        xs.sort();
+        got.sort();
+        assert_eq!(got, xs);
+    }
+
+    #[quickcheck]
+    fn one_shard_is_exact(xs: Vec<u32>) {
+        let q = MultiQueue::with_shards(1);
+        for x in &xs {
+            q.push(*x);
+        }
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(std::iter::from_fn(|| q.pop()).collect::<Vec<_>>(), xs);
+    }
+
+    #[test]
+    fn rank_error_is_small() {
+        let mut q = MultiQueue::with_shards(8);
+        q.extend(0..10_000u32);
+
+        // on average the popped element should be close to the true minimum
+        let mut error = 0;
+        for i in 0..5_000u32 {
+            let x = q.pop().unwrap();
+            error += x.abs_diff(i);
+        }
+        assert!(error / 5_000 < 100, "mean rank error {}", error / 5_000);
+    }
+
+    #[test]
+    fn concurrent_push_pop() {
+        let q = Arc::new(MultiQueue::with_shards(8));
+
+        let handles = (0..4u32)
+            .map(|t| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    for i in 0..1000 {
+                        q.push(i * 4 + t);
+                        if i % 2 == 0 {
+                            popped.extend(q.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut got = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        got.extend(std::iter::from_fn(|| q.pop()));
+        got.sort();
+
+        assert_eq!(got, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn is_send_sync() {
+        fn check<T: Send + Sync>() {}
+        check::<MultiQueue<u32>>();
+    }
+}
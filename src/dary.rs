@@ -0,0 +1,305 @@
+//! An implicit d-ary heap, kept in a flat `Vec` like
+//! [`std::collections::BinaryHeap`], but addressable.
+//!
+//! Each element remembers a slot in an arena, and the slot its position in
+//! the `Vec`, so a handle can find its element however it has been moved.
+//! Everything is `O(log n)` except `peek`, with no pointers to chase. A wider
+//! `D` makes the tree shallower, so `push` and `decrease_key` cheaper, at the
+//! cost of comparing more children on the way down in `pop`.
+use crate::arena::Arena;
+
+pub use crate::arena::Handle;
+
+pub struct DaryHeap<T, const D: usize> {
+    // the elements in heap order, each with its slot
+    data: Vec<(T, usize)>,
+    // the position in `data` of each element
+    slots: Arena<usize>,
+}
+
+impl<T: Ord, const D: usize> DaryHeap<T, D> {
+    pub fn new() -> Self {
+        const { assert!(D >= 2, "a heap needs at least two children a node") };
+        Self {
+            data: Vec::new(),
+            slots: Arena::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first().map(|(x, _)| x)
+    }
+
+    /// The element `handle` refers to, or `None` if the handle is stale.
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        let &i = self.slots.get(handle)?;
+        Some(&self.data[i].0)
+    }
+
+    pub fn push(&mut self, item: T) -> Handle {
+        let i = self.data.len();
+        let handle = self.slots.insert(i);
+        self.data.push((item, handle.index));
+        self.sift_up(i);
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        (!self.is_empty()).then(|| self.take(0))
+    }
+
+    /// Lowers the element `handle` refers to down to `item`.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `item` is greater than the element.
+    pub fn decrease_key(&mut self, handle: Handle, item: T) {
+        let &i = self.slots.get(handle).expect("handle is stale");
+        assert!(
+            item <= self.data[i].0,
+            "decrease_key would increase the key"
+        );
+        self.data[i].0 = item;
+        self.sift_up(i);
+    }
+
+    /// Takes out the element `handle` refers to, wherever it is.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale.
+    pub fn remove(&mut self, handle: Handle) -> T {
+        let &i = self.slots.get(handle).expect("handle is stale");
+        self.take(i)
+    }
+
+    /// Moves all the elements of `other` into `self`, and heapifies the lot
+    /// in `O(self.len() + other.len())`. Handles into `other` go stale.
+    pub fn meld(&mut self, other: Self) {
+        let len = self.data.len();
+        let base = self.slots.append(other.slots, |i, _| i + len);
+        self.data
+            .extend(other.data.into_iter().map(|(x, s)| (x, s + base)));
+        self.heapify();
+    }
+
+    /// Removes the element at position `i`, filling the gap with the last.
+    fn take(&mut self, i: usize) -> T {
+        let (item, slot) = self.data.swap_remove(i);
+        self.slots.remove(slot);
+
+        if i < self.data.len() {
+            self.slots[self.data[i].1] = i;
+            // the last element may belong above or below the gap
+            let i = self.sift_up(i);
+            self.sift_down(i);
+        }
+        item
+    }
+
+    /// Returns where the element ends up.
+    fn sift_up(&mut self, mut i: usize) -> usize {
+        while i > 0 {
+            let parent = (i - 1) / D;
+            if self.data[parent].0 <= self.data[i].0 {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+        i
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let first = D * i + 1;
+            let last = (first + D).min(self.data.len());
+            let least = |&a: &usize, &b: &usize| self.data[a].0.cmp(&self.data[b].0);
+            let Some(child) = (first..last).min_by(least) else {
+                return;
+            };
+
+            if self.data[i].0 <= self.data[child].0 {
+                return;
+            }
+            self.swap(i, child);
+            i = child;
+        }
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.data.swap(i, j);
+        self.slots[self.data[i].1] = i;
+        self.slots[self.data[j].1] = j;
+    }
+
+    fn heapify(&mut self) {
+        for i in (0..self.data.len().div_ceil(D)).rev() {
+            self.sift_down(i);
+        }
+    }
+}
+
+impl<T: Ord, const D: usize> Default for DaryHeap<T, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds the heap in `O(n)`, bottom up.
+impl<T: Ord, const D: usize> FromIterator<T> for DaryHeap<T, D> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::new();
+        for x in iter {
+            let slot = heap.slots.insert(heap.data.len()).index;
+            heap.data.push((x, slot));
+        }
+
+        heap.heapify();
+        heap
+    }
+}
+
+impl<T: Ord, const D: usize> Extend<T> for DaryHeap<T, D> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        if let (_, Some(upr)) = iter.size_hint() {
+            self.data.reserve(upr);
+            self.slots.reserve(upr);
+        }
+
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    fn verify<const D: usize>(heap: &DaryHeap<u32, D>) {
+        for (i, (x, slot)) in heap.data.iter().enumerate() {
+            assert_eq!(heap.slots[*slot], i);
+            if i > 0 {
+                assert!(heap.data[(i - 1) / D].0 <= *x);
+            }
+        }
+    }
+
+    fn drain<const D: usize>(mut heap: DaryHeap<u32, D>) -> Vec<u32> {
+        std::iter::from_fn(|| heap.pop()).collect()
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let mut exp = xs.clone();
+        exp.sort();
+
+        let heap = DaryHeap::<_, 2>::from_iter(xs.iter().copied());
+        verify(&heap);
+        assert_eq!(drain(heap), exp);
+
+        let heap = DaryHeap::<_, 3>::from_iter(xs.iter().copied());
+        verify(&heap);
+        assert_eq!(drain(heap), exp);
+
+        let mut heap = DaryHeap::<_, 8>::new();
+        heap.extend(xs.iter().copied());
+        verify(&heap);
+        assert_eq!(heap.len(), xs.len());
+        assert_eq!(drain(heap), exp);
+    }
+
+    #[quickcheck]
+    fn handles_match_model(ops: Vec<(u8, u32, usize)>) {
+        let mut heap = DaryHeap::<_, 4>::new();
+        // live handles, with their current values
+        let mut live: Vec<(Handle, u32)> = Vec::new();
+
+        for &(op, x, i) in &ops {
+            match op % 5 {
+                0 if !live.is_empty() => {
+                    let n = live.len();
+                    let (h, v) = &mut live[i % n];
+                    *v = x.min(*v);
+                    heap.decrease_key(*h, *v);
+                    assert_eq!(heap.get(*h), Some(&*v));
+                }
+                1 if !live.is_empty() => {
+                    let (h, v) = live.swap_remove(i % live.len());
+                    assert_eq!(heap.remove(h), v);
+                    assert_eq!(heap.get(h), None);
+                }
+                2 => {
+                    let min = live.iter().map(|(_, v)| *v).min();
+                    assert_eq!(heap.peek().copied(), min);
+
+                    let popped = heap.pop();
+                    assert_eq!(popped, min);
+                    // with duplicates, any handle of the value may go
+                    if popped.is_some() {
+                        let j = live.iter().position(|(h, _)| heap.get(*h).is_none());
+                        live.remove(j.unwrap());
+                    }
+                }
+                _ => live.push((heap.push(x), x)),
+            }
+
+            assert_eq!(heap.len(), live.len());
+            verify(&heap);
+        }
+
+        let mut rest = live.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        rest.sort();
+        assert_eq!(drain(heap), rest);
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = DaryHeap::<_, 4>::from_iter(xs.iter().copied());
+        a.pop();
+        let mut b = DaryHeap::from_iter(ys.iter().copied());
+        let h = b.push(u32::MAX);
+        b.decrease_key(h, 0);
+        a.meld(b);
+        verify(&a);
+        assert_eq!(a.get(h), None);
+
+        let mut exp = xs.clone();
+        exp.sort();
+        if !exp.is_empty() {
+            exp.remove(0);
+        }
+        exp.extend(ys);
+        exp.push(0);
+        exp.sort();
+        assert_eq!(drain(a), exp);
+    }
+
+    #[test]
+    #[should_panic = "handle is stale"]
+    fn remove_rejects_other_heaps_handles() {
+        let mut a = DaryHeap::<_, 2>::new();
+        let mut b = DaryHeap::<_, 2>::new();
+        a.push(3);
+        let h = b.push(3);
+        a.remove(h);
+    }
+
+    #[test]
+    #[should_panic = "handle is stale"]
+    fn remove_rejects_stale() {
+        let mut heap = DaryHeap::<_, 2>::new();
+        let h = heap.push(3);
+        heap.remove(h);
+        heap.remove(h);
+    }
+}
@@ -0,0 +1,65 @@
+//! Graphviz DOT rendering of a forest, shared by the heaps' `to_dot`.
+use std::fmt::Write;
+
+/// Renders the trees of `roots` in pre-order, highlighting the root at `min`.
+///
+/// Nodes are labelled with `label` and annotated with their degree. The roots
+/// are placed on the same rank, so they read left to right as in `roots`.
+pub(crate) fn forest_to_dot<N, T>(
+    roots: &[&N],
+    min: Option<usize>,
+    view: fn(&N) -> (&T, &[N]),
+    label: impl Fn(&T) -> String,
+) -> String {
+    let mut out = String::from("digraph FibonacciHeap {\n    node [shape=box];\n");
+    let mut ids = 0;
+    let mut root_ids = Vec::with_capacity(roots.len());
+
+    for (i, root) in roots.iter().enumerate() {
+        root_ids.push(ids);
+
+        // (id, node) pairs still to be written, parents before children
+        let mut stack = vec![(ids, *root)];
+        ids += 1;
+
+        while let Some((id, node)) = stack.pop() {
+            let (item, children) = view(node);
+            let highlight = if id == root_ids[i] && min == Some(i) {
+                ", style=filled, fillcolor=lightgrey"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                out,
+                "    n{id} [label=\"{}\\ndegree {}\"{highlight}];",
+                escape(&label(item)),
+                children.len(),
+            );
+
+            // reversed, so the first child is written first
+            let first = ids;
+            ids += children.len();
+            for (j, child) in children.iter().enumerate().rev() {
+                stack.push((first + j, child));
+            }
+            for j in 0..children.len() {
+                let _ = writeln!(out, "    n{id} -> n{};", first + j);
+            }
+        }
+    }
+
+    if root_ids.len() > 1 {
+        out.push_str("    { rank=same;");
+        for id in root_ids {
+            let _ = write!(out, " n{id};");
+        }
+        out.push_str(" }\n");
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
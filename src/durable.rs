@@ -0,0 +1,633 @@
+//! A durable priority queue backed by a write-ahead log.
+//!
+//! [`DurableHeap`] keeps a [`v2::FibonacciHeap`] in memory and appends every
+//! mutation to a log file _before_ applying it. Periodically the heap is
+//! compacted into a [snapshot](crate::snapshot) and the log restarted.
+//!
+//! The directory holds generations of `snapshot.<g>` and `wal.<g>` files. A
+//! generation's snapshot is the state at the start of its log, with
+//! generation 0 starting from an empty heap. On [`DurableHeap::open`] the
+//! newest snapshot is loaded and its log replayed. A torn record at the tail
+//! of the log (from a crash mid-write) is discarded. A snapshot is only
+//! renamed into place once fully written, so a damaged one is real
+//! corruption: `open` then fails rather than fall back to an older
+//! generation, which would silently drop the records logged since, and
+//! leaves the files be.
+//!
+//! Records are written to the operating system as they are appended, which
+//! survives the process crashing. Use [`DurableHeap::sync`] to also survive
+//! the machine crashing. If writing a record fails, the log is cut back to
+//! the last good record before the error is returned. Should that fail too,
+//! the heap refuses any further changes, as they would be logged after the
+//! torn bytes and so be lost on replay. The same goes for a compaction which
+//! fails after its snapshot is in place but before its log is.
+//!
+//! Compaction runs automatically from `push` and `pop`, once their record is
+//! logged. Should it fail, the operation still succeeds, since it is already
+//! durable, and compaction is tried again by the next one. Call
+//! [`DurableHeap::compact`] to see the error.
+//!
+//! [`v2::FibonacciHeap`]: crate::v2::FibonacciHeap
+use crate::snapshot::{crc32_update, invalid, Element};
+use crate::v2::FibonacciHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const WAL_MAGIC: [u8; 8] = *b"FIBHWAL\0";
+const WAL_VERSION: u16 = 1;
+const WAL_HEADER_LEN: u64 = 10;
+
+const TAG_PUSH: u8 = 1;
+const TAG_POP: u8 = 2;
+
+enum Record<T> {
+    Push(T),
+    Pop,
+}
+
+pub struct DurableHeap<T> {
+    heap: FibonacciHeap<T>,
+    dir: PathBuf,
+    generation: u64,
+    log: File,
+    // the end of the last good record in the log
+    end: u64,
+    // set once a torn record could not be cut from the log, or compaction
+    // could not switch to its new log
+    failed: bool,
+    // records in the current log
+    logged: usize,
+    compact_every: usize,
+}
+
+impl<T: Ord + Element> DurableHeap<T> {
+    /// Open (or create) a durable heap in the directory `path`, recovering
+    /// its state from the snapshot and log.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let dir = path.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        // newest generation first
+        let mut gens = list_generations(&dir)?;
+        gens.sort_unstable_by(|a, b| b.cmp(a));
+
+        let (generation, mut heap) = match gens.first() {
+            Some(&g) => match load_snapshot(&dir, g) {
+                Ok(heap) => (g, heap),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(invalid(format!("snapshot.{g} is truncated")))
+                }
+                Err(e) => return Err(e),
+            },
+            None => (0, FibonacciHeap::new()),
+        };
+
+        let logged = replay(&wal_path(&dir, generation), &mut heap)?;
+        let log = open_log(&wal_path(&dir, generation))?;
+        let end = log.metadata()?.len();
+
+        // older generations are left over from a compaction cut short
+        for &g in gens.iter().filter(|&&g| g < generation) {
+            remove_generation(&dir, g)?;
+        }
+        remove_if_exists(&dir.join("snapshot.tmp"))?;
+
+        Ok(Self {
+            heap,
+            dir,
+            generation,
+            log,
+            end,
+            failed: false,
+            logged,
+            compact_every: 10_000,
+        })
+    }
+
+    /// Compact automatically once the log holds `n` records.
+    pub fn set_compact_every(&mut self, n: usize) {
+        self.compact_every = n.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek()
+    }
+
+    pub fn push(&mut self, item: T) -> io::Result<()> {
+        let mut rec = vec![TAG_PUSH];
+        item.encode(&mut rec)?;
+        self.append(rec)?;
+
+        self.heap.push(item);
+        self.maybe_compact();
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> io::Result<Option<T>> {
+        if self.heap.is_empty() {
+            return Ok(None);
+        }
+
+        self.append(vec![TAG_POP])?;
+
+        let x = self.heap.pop();
+        self.maybe_compact();
+        Ok(x)
+    }
+
+    /// Flush the log to durable storage.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.log.sync_data()
+    }
+
+    /// Write the heap to a new snapshot and start a fresh log.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.check_failed()?;
+        let next = self.generation + 1;
+
+        // the snapshot only becomes visible once fully written
+        let tmp = self.dir.join("snapshot.tmp");
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        self.heap.write_snapshot(&mut w)?;
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, snapshot_path(&self.dir, next))?;
+
+        // open now loads the new snapshot and replays only its log, so any
+        // record written to the old log from here on would be lost
+        if let Err(e) = self.start_log(next) {
+            self.failed = true;
+            return Err(e);
+        }
+
+        remove_generation(&self.dir, next - 1)
+    }
+
+    /// Switches to the log of generation `next`, whose snapshot is in place.
+    fn start_log(&mut self, next: u64) -> io::Result<()> {
+        let log = open_log(&wal_path(&self.dir, next))?;
+        log.sync_all()?;
+        let end = log.metadata()?.len();
+        sync_dir(&self.dir)?;
+
+        self.log = log;
+        self.end = end;
+        self.generation = next;
+        self.logged = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, mut rec: Vec<u8>) -> io::Result<()> {
+        self.check_failed()?;
+
+        let crc = crc32_update(0, &rec);
+        crc.encode(&mut rec)?;
+        // a single write of the whole record, with nothing left buffered
+        if let Err(e) = self.log.write_all(&rec) {
+            self.cut_torn_record();
+            return Err(e);
+        }
+
+        self.end += rec.len() as u64;
+        self.logged += 1;
+        Ok(())
+    }
+
+    /// Drops whatever part of a failed record reached the log.
+    fn cut_torn_record(&mut self) {
+        if self.log.set_len(self.end).is_err() {
+            self.failed = true;
+        }
+    }
+
+    fn check_failed(&self) -> io::Result<()> {
+        match self.failed {
+            true => Err(io::Error::other(
+                "the log could not be repaired after a failed write",
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Compacts if the log is due. A failure is left for the next call to
+    /// try again, as the record which made the log due is already durable.
+    fn maybe_compact(&mut self) {
+        if self.logged >= self.compact_every {
+            let _ = self.compact();
+        }
+    }
+}
+
+fn snapshot_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("snapshot.{generation}"))
+}
+
+fn wal_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("wal.{generation}"))
+}
+
+fn list_generations(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut gens = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let g = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("snapshot."))
+            .and_then(|g| g.parse::<u64>().ok());
+        gens.extend(g);
+    }
+    Ok(gens)
+}
+
+fn remove_generation(dir: &Path, generation: u64) -> io::Result<()> {
+    remove_if_exists(&snapshot_path(dir, generation))?;
+    remove_if_exists(&wal_path(dir, generation))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn load_snapshot<T: Ord + Element>(dir: &Path, generation: u64) -> io::Result<FibonacciHeap<T>> {
+    let file = File::open(snapshot_path(dir, generation))?;
+    FibonacciHeap::read_snapshot(BufReader::new(file))
+}
+
+fn open_log(path: &Path) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .append(true)
+        .open(path)?;
+
+    if file.metadata()?.len() == 0 {
+        file.write_all(&WAL_MAGIC)?;
+        file.write_all(&WAL_VERSION.to_le_bytes())?;
+    }
+
+    Ok(file)
+}
+
+/// Replays the log at `path` onto `heap`, returning the number of records.
+///
+/// A torn or corrupt tail is truncated away.
+fn replay<T: Ord + Element>(path: &Path, heap: &mut FibonacciHeap<T>) -> io::Result<usize> {
+    let buf = match fs::read(path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    if buf.len() < WAL_HEADER_LEN as usize {
+        // crashed while writing the header, start afresh
+        fs::remove_file(path)?;
+        return Ok(0);
+    }
+    if buf[..8] != WAL_MAGIC {
+        return Err(invalid("not a heap log"));
+    }
+    if buf[8..10] != WAL_VERSION.to_le_bytes() {
+        return Err(invalid("unsupported log version"));
+    }
+
+    let mut pos = WAL_HEADER_LEN as usize;
+    let mut records = 0;
+    while pos < buf.len() {
+        match read_record::<T>(&buf[pos..]) {
+            Some((rec, n)) => {
+                match rec {
+                    Record::Push(x) => heap.push(x),
+                    Record::Pop if heap.pop().is_some() => (),
+                    Record::Pop => return Err(invalid("log pops an empty heap")),
+                }
+                pos += n;
+                records += 1;
+            }
+            None => {
+                // torn tail, drop it so new records follow the good ones
+                let file = OpenOptions::new().write(true).open(path)?;
+                file.set_len(pos as u64)?;
+                file.sync_all()?;
+                break;
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Decodes a record from the front of `buf`, along with the bytes consumed.
+/// Returns `None` if the record is incomplete or fails its checksum.
+fn read_record<T: Element>(buf: &[u8]) -> Option<(Record<T>, usize)> {
+    let mut r = buf;
+    let tag = u8::decode(&mut r).ok()?;
+    let rec = match tag {
+        TAG_PUSH => Record::Push(T::decode(&mut r).ok()?),
+        TAG_POP => Record::Pop,
+        _ => return None,
+    };
+
+    let n = buf.len() - r.len();
+    let crc = u32::decode(&mut r).ok()?;
+    if crc != crc32_update(0, &buf[..n]) {
+        return None;
+    }
+
+    Some((rec, n + 4))
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    // renames are only durable once the directory itself is synced, which is
+    // only possible (and needed) on unix
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static N: AtomicUsize = AtomicUsize::new(0);
+            let n = N.fetch_add(1, Ordering::Relaxed);
+            let path =
+                std::env::temp_dir().join(format!("fibheap-durable-{}-{}", std::process::id(), n));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn drain<T: Ord + Element>(heap: &mut DurableHeap<T>) -> Vec<T> {
+        std::iter::from_fn(|| heap.pop().unwrap()).collect()
+    }
+
+    #[quickcheck]
+    fn recovers_after_reopen(xs: Vec<u16>, pops: u8, compact_every: u8) {
+        let dir = TempDir::new();
+        let mut model = FibonacciHeap::new();
+
+        {
+            let mut heap = DurableHeap::open(&dir.0).unwrap();
+            heap.set_compact_every(compact_every as usize % 16 + 1);
+            for (i, x) in xs.iter().enumerate() {
+                heap.push(*x).unwrap();
+                model.push(*x);
+                if i % 3 == 0 && i / 3 < pops as usize {
+                    assert_eq!(heap.pop().unwrap(), model.pop());
+                }
+            }
+        }
+
+        let mut heap = DurableHeap::<u16>::open(&dir.0).unwrap();
+        assert_eq!(heap.len(), model.len());
+        let exp = std::iter::from_fn(|| model.pop()).collect::<Vec<_>>();
+        assert_eq!(drain(&mut heap), exp);
+    }
+
+    #[test]
+    fn compaction_starts_new_generation() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.set_compact_every(4);
+
+        for x in 0..10u32 {
+            heap.push(x).unwrap();
+        }
+        assert_eq!(heap.generation, 2);
+        assert_eq!(heap.logged, 2);
+        drop(heap);
+
+        let mut files = fs::read_dir(&dir.0)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, ["snapshot.2", "wal.2"]);
+
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(drain(&mut heap), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn torn_tail_is_discarded() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        for x in [5u32, 3, 4] {
+            heap.push(x).unwrap();
+        }
+        drop(heap);
+
+        // chop the last push record in half
+        let wal = wal_path(&dir.0, 0);
+        let len = fs::metadata(&wal).unwrap().len();
+        let f = OpenOptions::new().write(true).open(&wal).unwrap();
+        f.set_len(len - 3).unwrap();
+        drop(f);
+
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        assert_eq!(heap.len(), 2);
+        // new records land after the last good one
+        heap.push(1).unwrap();
+        drop(heap);
+
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(drain(&mut heap), [1, 3, 5]);
+    }
+
+    #[test]
+    fn failed_write_is_cut_from_the_log() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.push(5u32).unwrap();
+
+        // half a record reaches the log before the write fails
+        heap.log.write_all(&[TAG_PUSH, 7, 0]).unwrap();
+        heap.cut_torn_record();
+        assert!(!heap.failed);
+
+        // so later records are not lost behind it
+        heap.push(3).unwrap();
+        heap.pop().unwrap();
+        heap.push(4).unwrap();
+        drop(heap);
+
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(drain(&mut heap), [4, 5]);
+    }
+
+    #[test]
+    fn refuses_writes_after_an_unrepaired_failure() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.push(5u32).unwrap();
+        heap.failed = true;
+
+        assert!(heap.push(3).is_err());
+        assert!(heap.pop().is_err());
+        assert!(heap.compact().is_err());
+        assert_eq!(heap.len(), 1);
+    }
+
+    #[test]
+    fn corrupt_record_is_discarded() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        for x in [5u32, 3, 4] {
+            heap.push(x).unwrap();
+        }
+        drop(heap);
+
+        let wal = wal_path(&dir.0, 0);
+        let mut buf = fs::read(&wal).unwrap();
+        let n = buf.len();
+        buf[n - 5] ^= 0xff; // payload of the last record
+        fs::write(&wal, buf).unwrap();
+
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(drain(&mut heap), [3, 5]);
+    }
+
+    #[test]
+    fn interrupted_compaction_is_ignored() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.set_compact_every(3);
+        for x in [5u32, 3, 4, 1] {
+            heap.push(x).unwrap();
+        }
+        drop(heap);
+
+        // a half-written snapshot, which was never renamed into place
+        let good = fs::read(snapshot_path(&dir.0, 1)).unwrap();
+        let tmp = dir.0.join("snapshot.tmp");
+        fs::write(&tmp, &good[..good.len() / 2]).unwrap();
+
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(heap.generation, 1);
+        assert!(!tmp.exists());
+        assert_eq!(drain(&mut heap), [1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn corrupt_newest_snapshot_fails_to_open() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.set_compact_every(3);
+        for x in [5u32, 3, 4, 1] {
+            heap.push(x).unwrap();
+        }
+        drop(heap);
+
+        // falling back to generation 1 would drop the records in wal.2
+        let good = fs::read(snapshot_path(&dir.0, 1)).unwrap();
+        fs::write(snapshot_path(&dir.0, 2), &good[..good.len() / 2]).unwrap();
+        fs::copy(wal_path(&dir.0, 1), wal_path(&dir.0, 2)).unwrap();
+
+        let err = DurableHeap::<u32>::open(&dir.0).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // nothing was deleted
+        for g in [1, 2] {
+            assert!(snapshot_path(&dir.0, g).exists());
+            assert!(wal_path(&dir.0, g).exists());
+        }
+    }
+
+    #[test]
+    fn failed_compaction_keeps_the_operation() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.set_compact_every(2);
+
+        // the snapshot can't be written
+        let tmp = dir.0.join("snapshot.tmp");
+        fs::create_dir(&tmp).unwrap();
+        heap.push(5u32).unwrap();
+        heap.push(3).unwrap();
+        assert_eq!(heap.pop().unwrap(), Some(3));
+        assert_eq!(heap.generation, 0);
+        assert!(heap.compact().is_err());
+
+        // and is tried again by the next operation
+        fs::remove_dir(&tmp).unwrap();
+        heap.push(4).unwrap();
+        assert_eq!(heap.generation, 1);
+        drop(heap);
+
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(drain(&mut heap), [4, 5]);
+    }
+
+    #[test]
+    fn failed_switch_to_the_new_log_refuses_writes() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.push(5u32).unwrap();
+        heap.push(3).unwrap();
+
+        // the snapshot goes into place, but its log can't be opened
+        fs::create_dir(wal_path(&dir.0, 1)).unwrap();
+        assert!(heap.compact().is_err());
+        assert!(snapshot_path(&dir.0, 1).exists());
+        assert!(heap.failed);
+
+        // so nothing more goes to the old log, where it would be lost
+        let old = fs::read(wal_path(&dir.0, 0)).unwrap();
+        assert!(heap.push(4).is_err());
+        assert!(heap.pop().is_err());
+        assert_eq!(fs::read(wal_path(&dir.0, 0)).unwrap(), old);
+        drop(heap);
+
+        fs::remove_dir(wal_path(&dir.0, 1)).unwrap();
+        let mut heap = DurableHeap::<u32>::open(&dir.0).unwrap();
+        assert_eq!(heap.generation, 1);
+        assert_eq!(drain(&mut heap), [3, 5]);
+    }
+
+    #[test]
+    fn corrupt_only_snapshot_fails_to_open() {
+        let dir = TempDir::new();
+        let mut heap = DurableHeap::open(&dir.0).unwrap();
+        heap.set_compact_every(3);
+        for x in [5u32, 3, 4, 1] {
+            heap.push(x).unwrap();
+        }
+        drop(heap);
+
+        let path = snapshot_path(&dir.0, 1);
+        let mut buf = fs::read(&path).unwrap();
+        let n = buf.len();
+        buf[n / 2] ^= 1;
+        fs::write(&path, &buf).unwrap();
+
+        let err = DurableHeap::<u32>::open(&dir.0).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // nothing was deleted or overwritten
+        assert_eq!(fs::read(&path).unwrap(), buf);
+        assert!(wal_path(&dir.0, 1).exists());
+    }
+}
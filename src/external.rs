@@ -0,0 +1,660 @@
+//! An external-memory heap which spills to disk.
+//!
+//! [`SpillingHeap`] keeps at most a fixed number of elements in an in-memory
+//! [`v2::FibonacciHeap`]. Once over budget, the in-memory heap is drained into
+//! a sorted _run_ on disk. Runs are merged back lazily: only the head of each
+//! run is held in memory, and [`SpillingHeap::pop`] takes the lesser of the
+//! in-memory minimum and the least run head.
+//!
+//! To bound the number of open files, runs of similar length are merged into
+//! one once there are more than [`MAX_RUNS`] runs.
+//!
+//! Should writing a run fail, the heap is left as it was, and
+//! [`SpillingHeap::push`] hands the element back in a [`PushError`]. Should
+//! reading the next element of a run fail in [`SpillingHeap::pop`], the run
+//! is rewound and kept, so the pop can be tried again. Only if the run can't
+//! be rewound either are its elements dropped, and no longer counted.
+//!
+//! [`v2::FibonacciHeap`]: crate::v2::FibonacciHeap
+use crate::snapshot::Element;
+use crate::v2::FibonacciHeap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicU64};
+
+pub const MAX_RUNS: usize = 64;
+
+/// Returned by [`SpillingHeap::push`] when the heap is full and spilling it
+/// to disk failed, giving back the element.
+#[derive(Debug)]
+pub struct PushError<T>(pub T, pub io::Error);
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spilling to disk failed: {}", self.1)
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for PushError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.1)
+    }
+}
+
+/// Encodes elements into, and decodes them from, spilled runs.
+pub trait Codec<T> {
+    fn encode<W: Write>(&self, item: &T, w: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(&self, r: &mut R) -> io::Result<T>;
+}
+
+/// A [`Codec`] using the [`Element`] encoding of snapshots.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ElementCodec;
+
+impl<T: Element> Codec<T> for ElementCodec {
+    fn encode<W: Write>(&self, item: &T, w: &mut W) -> io::Result<()> {
+        item.encode(w)
+    }
+
+    fn decode<R: Read>(&self, r: &mut R) -> io::Result<T> {
+        T::decode(r)
+    }
+}
+
+pub struct SpillingHeap<T, C = ElementCodec> {
+    mem: FibonacciHeap<T>,
+    limit: usize,
+    runs: FibonacciHeap<Run<T>>,
+    codec: C,
+    dir: PathBuf,
+    len: usize,
+}
+
+impl<T: Ord + Element> SpillingHeap<T> {
+    /// Create a heap holding at most `limit` elements in memory, spilling to
+    /// the system's temporary directory.
+    pub fn new(limit: usize) -> Self {
+        Self::with_codec(limit, ElementCodec)
+    }
+}
+
+impl<T: Ord, C: Codec<T>> SpillingHeap<T, C> {
+    pub fn with_codec(limit: usize, codec: C) -> Self {
+        Self {
+            mem: FibonacciHeap::new(),
+            limit: limit.max(1),
+            runs: FibonacciHeap::new(),
+            codec,
+            dir: std::env::temp_dir(),
+            len: 0,
+        }
+    }
+
+    /// Spill runs into `dir` rather than the temporary directory.
+    pub fn spill_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of elements currently held in memory (excluding run heads).
+    pub fn in_memory(&self) -> usize {
+        self.mem.len()
+    }
+
+    /// The number of sorted runs on disk.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        match (self.mem.peek(), self.runs.peek()) {
+            (Some(a), Some(b)) if b.head <= *a => Some(&b.head),
+            (Some(a), _) => Some(a),
+            (None, b) => b.map(|r| &r.head),
+        }
+    }
+
+    /// Spills the in-memory heap first if it is full. Should that fail,
+    /// `item` is not pushed, and comes back in the error.
+    pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+        if self.mem.len() >= self.limit {
+            if let Err(e) = self.spill() {
+                return Err(PushError(item, e));
+            }
+        }
+
+        self.mem.push(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> io::Result<Option<T>> {
+        let from_run = match (self.mem.peek(), self.runs.peek()) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(a), Some(b)) => b.head < *a,
+        };
+
+        let x = if from_run {
+            let run = self.runs.pop().expect("not empty");
+            let count = run.remaining as usize + 1;
+            match run.advance(&self.codec) {
+                Ok((x, run)) => {
+                    self.runs.extend(run);
+                    Some(x)
+                }
+                Err((e, Some(run))) => {
+                    self.runs.push(run);
+                    return Err(e);
+                }
+                Err((e, None)) => {
+                    self.len -= count;
+                    return Err(e);
+                }
+            }
+        } else {
+            self.mem.pop()
+        };
+
+        self.len -= x.is_some() as usize;
+        Ok(x)
+    }
+
+    /// Drain the in-memory heap into a new sorted run. The elements go back
+    /// into memory if the run can't be written.
+    fn spill(&mut self) -> io::Result<()> {
+        let sorted = std::iter::from_fn(|| self.mem.pop()).collect::<Vec<_>>();
+        match self.write_run(&sorted) {
+            Ok(run) => self.runs.extend(run),
+            Err(e) => {
+                self.mem.extend(sorted);
+                return Err(e);
+            }
+        }
+
+        if self.runs.len() > MAX_RUNS {
+            self.merge_runs()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_run(&self, sorted: &[T]) -> io::Result<Option<Run<T>>> {
+        let mut w = RunWriter::create(&self.dir)?;
+        for x in sorted {
+            w.write(&self.codec, x)?;
+        }
+        w.finish(&self.codec)
+    }
+
+    /// Merge the runs of the most crowded tier, where a tier holds the runs
+    /// whose lengths agree to within a factor of [`MAX_RUNS`] / 2. Merging
+    /// runs of similar length means each element is rewritten a logarithmic
+    /// number of times, rather than at every merge.
+    fn merge_runs(&mut self) -> io::Result<()> {
+        let tier = |r: &Run<T>| (r.remaining + 1).ilog(MAX_RUNS as u64 / 2);
+
+        let runs = std::iter::from_fn(|| self.runs.pop()).collect::<Vec<_>>();
+        let mut crowds = BTreeMap::<u32, usize>::new();
+        for run in &runs {
+            *crowds.entry(tier(run)).or_default() += 1;
+        }
+        // on a tie, the lowest tier is the cheapest to merge
+        let (&t, _) = crowds
+            .iter()
+            .max_by_key(|&(&t, &n)| (n, Reverse(t)))
+            .expect("more than MAX_RUNS runs");
+
+        let (mut runs, rest): (Vec<_>, Vec<_>) = runs.into_iter().partition(|r| tier(r) == t);
+        self.runs.extend(rest);
+
+        match self.merge(&mut runs) {
+            // dropping the merged runs removes their files
+            Ok(run) => self.runs.extend(run),
+            Err(e) => {
+                self.runs.extend(runs);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// k-way merge `runs` into a new run, reading them through files of its
+    /// own so that the runs are left as they were.
+    fn merge(&self, runs: &mut [Run<T>]) -> io::Result<Option<Run<T>>> {
+        let mut w = RunWriter::create(&self.dir)?;
+
+        let mut cursors = FibonacciHeap::new();
+        for run in runs {
+            cursors.push(Cursor::new(run)?);
+        }
+
+        while let Some(c) = cursors.pop() {
+            w.write(&self.codec, c.head())?;
+            cursors.extend(c.advance(&self.codec)?);
+        }
+
+        w.finish(&self.codec)
+    }
+}
+
+/// A sorted run on disk, ordered by its head element.
+struct Run<T> {
+    head: T,
+    remaining: u64,
+    reader: BufReader<File>,
+    path: TempPath,
+}
+
+impl<T> Run<T> {
+    /// Take the head, reading the next element if there is one. Should the
+    /// read fail, the run is rewound and handed back with the error, unless
+    /// it can't be rewound.
+    fn advance<C: Codec<T>>(mut self, codec: &C) -> Result<(T, Option<Self>), Failed<Self>> {
+        if self.remaining == 0 {
+            // dropping the path removes the file
+            let Run { head, .. } = self;
+            return Ok((head, None));
+        }
+
+        let pos = match self.reader.stream_position() {
+            Ok(pos) => pos,
+            Err(e) => return Err((e, Some(self))),
+        };
+        match codec.decode(&mut self.reader) {
+            Ok(next) => {
+                self.remaining -= 1;
+                let head = std::mem::replace(&mut self.head, next);
+                Ok((head, Some(self)))
+            }
+            // a failed read may have stopped anywhere in the element
+            Err(e) => match self.reader.seek(SeekFrom::Start(pos)) {
+                Ok(_) => Err((e, Some(self))),
+                Err(_) => Err((e, None)),
+            },
+        }
+    }
+}
+
+/// An error, and the run it happened in if that is still usable.
+type Failed<R> = (io::Error, Option<R>);
+
+/// A read-only view of a run, picking up from where the run's own reader is.
+struct Cursor<'a, T> {
+    first: &'a T,
+    next: Option<T>,
+    remaining: u64,
+    reader: BufReader<File>,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    fn new(run: &'a mut Run<T>) -> io::Result<Self> {
+        let pos = run.reader.stream_position()?;
+        let mut file = File::open(&run.path.0)?;
+        file.seek(SeekFrom::Start(pos))?;
+
+        Ok(Self {
+            first: &run.head,
+            next: None,
+            remaining: run.remaining,
+            reader: BufReader::new(file),
+        })
+    }
+
+    fn head(&self) -> &T {
+        self.next.as_ref().unwrap_or(self.first)
+    }
+
+    fn advance<C: Codec<T>>(mut self, codec: &C) -> io::Result<Option<Self>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.next = Some(codec.decode(&mut self.reader)?);
+        self.remaining -= 1;
+        Ok(Some(self))
+    }
+}
+
+/// A file path which is removed on drop.
+struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+impl<T: Ord> PartialEq for Run<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.head == other.head
+    }
+}
+
+impl<T: Ord> Eq for Run<T> {}
+
+impl<T: Ord> PartialOrd for Run<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Run<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.head.cmp(&other.head)
+    }
+}
+
+impl<T: Ord> PartialEq for Cursor<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.head() == other.head()
+    }
+}
+
+impl<T: Ord> Eq for Cursor<'_, T> {}
+
+impl<T: Ord> PartialOrd for Cursor<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Cursor<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.head().cmp(other.head())
+    }
+}
+
+struct RunWriter {
+    w: BufWriter<File>,
+    path: TempPath,
+    count: u64,
+}
+
+impl RunWriter {
+    fn create(dir: &Path) -> io::Result<Self> {
+        static N: AtomicU64 = AtomicU64::new(0);
+        let n = N.fetch_add(1, atomic::Ordering::Relaxed);
+        let path = dir.join(format!("fibheap-spill-{}-{}.run", std::process::id(), n));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        Ok(Self {
+            w: BufWriter::new(file),
+            path: TempPath(path),
+            count: 0,
+        })
+    }
+
+    fn write<T, C: Codec<T>>(&mut self, codec: &C, item: &T) -> io::Result<()> {
+        self.count += 1;
+        codec.encode(item, &mut self.w)
+    }
+
+    /// Rewind the file and read back the first element as the head.
+    fn finish<T, C: Codec<T>>(self, codec: &C) -> io::Result<Option<Run<T>>> {
+        let RunWriter { w, path, count } = self;
+        let mut file = w.into_inner().map_err(|e| e.into_error())?;
+
+        if count == 0 {
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(file);
+        let head = codec.decode(&mut reader)?;
+
+        Ok(Some(Run {
+            head,
+            remaining: count - 1,
+            reader,
+            path,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use std::cell::Cell;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static N: AtomicU64 = AtomicU64::new(0);
+            let n = N.fetch_add(1, atomic::Ordering::Relaxed);
+            let path =
+                std::env::temp_dir().join(format!("fibheap-external-{}-{}", std::process::id(), n));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn files(&self) -> usize {
+            fs::read_dir(&self.0).unwrap().count()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[quickcheck]
+    fn pops_by_min_across_memory_and_disk(xs: Vec<u32>, limit: u8) {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::new(limit as usize % 16 + 1).spill_dir(&dir.0);
+
+        let mut model = FibonacciHeap::new();
+        for (i, x) in xs.iter().enumerate() {
+            heap.push(*x).unwrap();
+            model.push(*x);
+            if i % 5 == 0 {
+                assert_eq!(heap.pop().unwrap(), model.pop());
+            }
+            assert!(heap.in_memory() <= limit as usize % 16 + 1);
+        }
+
+        assert_eq!(heap.len(), model.len());
+        while let Some(x) = model.pop() {
+            assert_eq!(heap.peek(), Some(&x));
+            assert_eq!(heap.pop().unwrap(), Some(x));
+        }
+        assert_eq!(heap.pop().unwrap(), None);
+
+        // exhausted runs clean up their files
+        assert_eq!(dir.files(), 0);
+    }
+
+    #[test]
+    fn runs_are_merged_and_removed_on_drop() {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::new(1).spill_dir(&dir.0);
+
+        for x in (0..1000u32).rev() {
+            heap.push(x).unwrap();
+        }
+        assert!(heap.runs() <= MAX_RUNS);
+        assert_eq!(dir.files(), heap.runs());
+
+        for x in 0..10 {
+            assert_eq!(heap.pop().unwrap(), Some(x));
+        }
+
+        drop(heap);
+        assert_eq!(dir.files(), 0);
+    }
+
+    // counts encodes, and fails them once `budget` runs out; fails decodes
+    // partway through while `fail_reads` is set
+    #[derive(Default)]
+    struct Counting {
+        encodes: Cell<usize>,
+        budget: Cell<Option<usize>>,
+        fail_reads: Cell<bool>,
+    }
+
+    impl Codec<u32> for Counting {
+        fn encode<W: Write>(&self, item: &u32, w: &mut W) -> io::Result<()> {
+            if let Some(n) = self.budget.get() {
+                if n == 0 {
+                    return Err(io::Error::other("out of budget"));
+                }
+                self.budget.set(Some(n - 1));
+            }
+            self.encodes.set(self.encodes.get() + 1);
+            ElementCodec.encode(item, w)
+        }
+
+        fn decode<R: Read>(&self, r: &mut R) -> io::Result<u32> {
+            if self.fail_reads.get() {
+                r.read_exact(&mut [0])?;
+                return Err(io::Error::other("failed read"));
+            }
+            ElementCodec.decode(r)
+        }
+    }
+
+    fn drain<C: Codec<u32>>(heap: &mut SpillingHeap<u32, C>) -> Vec<u32> {
+        std::iter::from_fn(|| heap.pop().unwrap()).collect()
+    }
+
+    #[test]
+    fn failed_spill_hands_back_the_element() {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::with_codec(4, Counting::default()).spill_dir(&dir.0);
+
+        // one run on disk, and a full heap in memory
+        for x in 0..8 {
+            heap.push(x).unwrap();
+        }
+        assert_eq!((heap.runs(), heap.in_memory()), (1, 4));
+
+        heap.codec.budget.set(Some(2));
+        for x in 8..11 {
+            assert_eq!(heap.push(x).unwrap_err().0, x);
+        }
+        assert_eq!(heap.len(), 8);
+        assert_eq!((heap.runs(), heap.in_memory()), (1, 4));
+        assert_eq!(dir.files(), 1);
+
+        heap.codec.budget.set(None);
+        for x in 8..11 {
+            heap.push(x).unwrap();
+        }
+        assert_eq!(heap.runs(), 2);
+        assert_eq!(drain(&mut heap), (0..11).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn failed_merge_hands_back_the_element() {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::with_codec(1, Counting::default()).spill_dir(&dir.0);
+
+        let mut x = 0;
+        while heap.runs() < MAX_RUNS {
+            heap.push(x).unwrap();
+            x += 1;
+        }
+        // enough for the spill, but not for the merge after it
+        heap.codec.budget.set(Some(1));
+        assert_eq!(heap.push(x).unwrap_err().0, x);
+        assert_eq!(heap.runs(), MAX_RUNS + 1);
+        assert_eq!(dir.files(), MAX_RUNS + 1);
+        assert_eq!(heap.len(), x as usize);
+
+        // the spill went through, so there is room for it now
+        heap.push(x).unwrap();
+        heap.codec.budget.set(None);
+        assert_eq!(drain(&mut heap), (0..=x).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn failed_read_keeps_the_run() {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::with_codec(2, Counting::default()).spill_dir(&dir.0);
+        for x in 0..6 {
+            heap.push(x).unwrap();
+        }
+        assert_eq!(heap.runs(), 2);
+
+        heap.codec.fail_reads.set(true);
+        assert!(heap.pop().is_err());
+        assert!(heap.pop().is_err());
+        assert_eq!(heap.len(), 6);
+        assert_eq!(heap.runs(), 2);
+        assert_eq!(heap.peek(), Some(&0));
+
+        heap.codec.fail_reads.set(false);
+        assert_eq!(drain(&mut heap), (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn merges_rewrite_each_element_a_few_times() {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::with_codec(1, Counting::default()).spill_dir(&dir.0);
+
+        let n = 20_000;
+        for x in (0..n).rev() {
+            heap.push(x).unwrap();
+        }
+        assert!(heap.runs() <= MAX_RUNS);
+        assert!(heap.codec.encodes.get() < 4 * n as usize);
+        assert_eq!(drain(&mut heap), (0..n).collect::<Vec<_>>());
+    }
+
+    // encode strings as newline-terminated text
+    struct Lines;
+
+    impl Codec<String> for Lines {
+        fn encode<W: Write>(&self, item: &String, w: &mut W) -> io::Result<()> {
+            writeln!(w, "{item}")
+        }
+
+        fn decode<R: Read>(&self, r: &mut R) -> io::Result<String> {
+            let mut buf = Vec::new();
+            let mut b = [0];
+            loop {
+                r.read_exact(&mut b)?;
+                if b[0] == b'\n' {
+                    break;
+                }
+                buf.push(b[0]);
+            }
+            String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    #[test]
+    fn custom_codec() {
+        let dir = TempDir::new();
+        let mut heap = SpillingHeap::with_codec(2, Lines).spill_dir(&dir.0);
+
+        for s in ["pear", "apple", "fig", "kiwi", "banana", "cherry"] {
+            heap.push(s.to_string()).unwrap();
+        }
+        assert!(heap.runs() > 0);
+
+        let got = std::iter::from_fn(|| heap.pop().unwrap()).collect::<Vec<_>>();
+        assert_eq!(got, ["apple", "banana", "cherry", "fig", "kiwi", "pear"]);
+    }
+}
@@ -0,0 +1,380 @@
+//! A hollow heap, after Hansen, Kaplan, Tarjan and Zwick.
+//!
+//! [`HollowHeap::decrease_key`] doesn't cut the node out. It moves the
+//! element to a new node linked in at the root, and leaves the old node
+//! behind, _hollow_, with the new node as a second parent. So the heap is a
+//! DAG, with one full node per element and any number of hollow ones. Hollow
+//! nodes are only cleaned up once they surface as roots during
+//! [`HollowHeap::pop`], which also links the full roots by rank.
+//!
+//! As with Fibonacci heaps, `pop` is `O(log n)` amortised and `push` and
+//! `decrease_key` `O(1)`, but each step is simpler: there are no marks, no
+//! cuts and no parent pointers. [`HollowHeap::meld`] is not `O(1)`: nodes
+//! live in an arena, so the other heap's are copied in.
+use crate::arena::Arena;
+
+pub use crate::arena::Handle;
+
+pub struct HollowHeap<T> {
+    nodes: Arena<Node>,
+    // elements live apart from the nodes, as they move to a new node on
+    // every decrease_key
+    entries: Arena<Entry<T>>,
+    root: Option<usize>,
+    len: usize,
+    // full roots by rank while popping, kept to avoid allocating on every pop
+    ranks: Vec<Option<usize>>,
+}
+
+struct Entry<T> {
+    item: T,
+    node: usize,
+}
+
+struct Node {
+    // the element, or none if the node is hollow
+    entry: Option<usize>,
+    rank: usize,
+    child: Option<usize>,
+    next: Option<usize>,
+    // the second parent of a hollow node, whose last child it is; `next`
+    // belongs to the first parent's list
+    second: Option<usize>,
+}
+
+impl<T: Ord> HollowHeap<T> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Arena::new(),
+            entries: Arena::new(),
+            root: None,
+            len: 0,
+            ranks: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.map(|r| self.item(r))
+    }
+
+    /// The element `handle` refers to, or `None` if the handle is stale.
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.entries.get(handle).map(|e| &e.item)
+    }
+
+    pub fn push(&mut self, item: T) -> Handle {
+        let node = self.nodes.insert(Node::new(0)).index;
+        let handle = self.entries.insert(Entry { item, node });
+        self.nodes[node].entry = Some(handle.index);
+
+        self.root = Some(match self.root {
+            Some(r) => self.link(r, node),
+            None => node,
+        });
+        self.len += 1;
+
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let root = self.root.take()?;
+        let entry = self.nodes[root].entry.take().expect("the root is full");
+        self.len -= 1;
+
+        // hollow nodes without parents left, linked through `next`
+        let mut hollow = Some(root);
+        self.nodes[root].next = None;
+        while let Some(v) = hollow {
+            let node = self.nodes.remove(v);
+            hollow = node.next;
+
+            let mut next = node.child;
+            while let Some(u) = next {
+                let child = &mut self.nodes[u];
+                next = child.next;
+
+                if child.entry.is_some() {
+                    self.link_ranked(u);
+                    continue;
+                }
+                match child.second.take() {
+                    None => {
+                        child.next = hollow;
+                        hollow = Some(u);
+                    }
+                    // u was the last child of v, its next is in another list
+                    Some(p) if p == v => next = None,
+                    // u stays the last child of its second parent
+                    Some(_) => child.next = None,
+                }
+            }
+        }
+
+        // link the full roots, whatever their rank
+        let mut ranks = std::mem::take(&mut self.ranks);
+        for u in ranks.iter_mut().filter_map(Option::take) {
+            self.root = Some(match self.root {
+                Some(r) => self.link(r, u),
+                None => u,
+            });
+        }
+        self.ranks = ranks;
+
+        Some(self.entries.remove(entry).item)
+    }
+
+    /// Lowers the element `handle` refers to down to `item`.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `item` is greater than the element.
+    pub fn decrease_key(&mut self, handle: Handle, item: T) {
+        let entry = self.entries.get(handle).expect("handle is stale");
+        assert!(item <= entry.item, "decrease_key would increase the key");
+        let u = entry.node;
+        self.entries[handle.index].item = item;
+
+        let root = self.root.expect("not empty");
+        if u == root {
+            return;
+        }
+
+        // move the element to a new node, which u hangs off as a hollow
+        // child, and link that in at the root
+        let rank = self.nodes[u].rank.saturating_sub(2);
+        let v = self.nodes.insert(Node::new(rank)).index;
+        self.nodes[v].entry = self.nodes[u].entry.take();
+        self.nodes[v].child = Some(u);
+        self.nodes[u].second = Some(v);
+        self.entries[handle.index].node = v;
+
+        self.root = Some(self.link(v, root));
+    }
+
+    /// Moves all the elements of `other` into `self`.
+    ///
+    /// The nodes of `other` are copied over, so this costs `O(other.len())`
+    /// plus any hollow nodes `other` has. Handles into `self` stay valid, and
+    /// handles into `other` go stale.
+    pub fn meld(&mut self, other: Self) {
+        let base = self.nodes.len();
+        let entry_base = self.entries.len();
+        let shift = |i: Option<usize>| i.map(|i| i + base);
+
+        self.nodes.append(other.nodes, |n, _| Node {
+            entry: n.entry.map(|e| e + entry_base),
+            child: shift(n.child),
+            next: shift(n.next),
+            second: shift(n.second),
+            ..n
+        });
+        self.entries.append(other.entries, |e, _| Entry {
+            node: e.node + base,
+            ..e
+        });
+
+        self.len += other.len;
+        self.root = match (self.root, shift(other.root)) {
+            (Some(a), Some(b)) => Some(self.link(a, b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// Links a full node into the roots by rank, carrying as it goes.
+    fn link_ranked(&mut self, mut u: usize) {
+        loop {
+            let r = self.nodes[u].rank;
+            if self.ranks.len() <= r {
+                self.ranks.resize(r + 1, None);
+            }
+            match self.ranks[r].take() {
+                Some(w) => {
+                    u = self.link(u, w);
+                    self.nodes[u].rank += 1;
+                }
+                None => {
+                    self.ranks[r] = Some(u);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Links two full roots, the greater becoming the first child of the
+    /// lesser. Returns the new root.
+    fn link(&mut self, a: usize, b: usize) -> usize {
+        let (parent, child) = if self.item(a) <= self.item(b) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        self.nodes[child].next = self.nodes[parent].child;
+        self.nodes[parent].child = Some(child);
+        parent
+    }
+
+    fn item(&self, i: usize) -> &T {
+        let entry = self.nodes[i].entry.expect("node is full");
+        &self.entries[entry].item
+    }
+}
+
+impl Node {
+    fn new(rank: usize) -> Self {
+        Self {
+            entry: None,
+            rank,
+            child: None,
+            next: None,
+            second: None,
+        }
+    }
+}
+
+impl<T: Ord> Default for HollowHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for HollowHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Ord> Extend<T> for HollowHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        if let (_, Some(upr)) = iter.size_hint() {
+            self.nodes.reserve(upr);
+            self.entries.reserve(upr);
+        }
+
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    // counts the nodes reachable from the root, each once
+    fn nodes(heap: &HollowHeap<u32>) -> usize {
+        let mut seen = std::collections::HashSet::new();
+        let mut stack = heap.root.into_iter().collect::<Vec<_>>();
+        while let Some(v) = stack.pop() {
+            if !seen.insert(v) {
+                continue;
+            }
+            let mut next = heap.nodes[v].child;
+            while let Some(u) = next {
+                stack.push(u);
+                next = match heap.nodes[u].second {
+                    Some(p) if p == v => None,
+                    _ => heap.nodes[u].next,
+                };
+            }
+        }
+        seen.len()
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let mut heap = HollowHeap::from_iter(xs.iter().copied());
+        assert_eq!(heap.len(), xs.len());
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), xs);
+    }
+
+    #[quickcheck]
+    fn decrease_key_matches_model(ops: Vec<(u8, u32, usize)>) {
+        let mut heap = HollowHeap::new();
+        // live handles, with their current values
+        let mut live: Vec<(Handle, u32)> = Vec::new();
+        let mut decreases = 0;
+
+        for &(op, x, i) in &ops {
+            match op % 4 {
+                0 | 1 if !live.is_empty() => {
+                    let n = live.len();
+                    let (h, v) = &mut live[i % n];
+                    *v = x.min(*v);
+                    heap.decrease_key(*h, *v);
+                    assert_eq!(heap.get(*h), Some(&*v));
+                    decreases += 1;
+                }
+                2 => {
+                    let min = live.iter().map(|(_, v)| *v).min();
+                    assert_eq!(heap.peek().copied(), min);
+
+                    let popped = heap.pop();
+                    assert_eq!(popped, min);
+                    // with duplicates, any handle of the value may go
+                    if popped.is_some() {
+                        let j = live.iter().position(|(h, _)| heap.get(*h).is_none());
+                        live.remove(j.unwrap());
+                    }
+                }
+                _ => live.push((heap.push(x), x)),
+            }
+
+            assert_eq!(heap.len(), live.len());
+            // every element has a full node, and a decrease leaves at most
+            // one hollow node behind
+            let n = nodes(&heap);
+            assert!(live.len() <= n && n <= live.len() + decreases);
+        }
+
+        let mut rest = live.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        rest.sort();
+        assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), rest);
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = HollowHeap::from_iter(xs.iter().copied());
+        a.pop();
+        let g = a.push(7);
+        let mut b = HollowHeap::from_iter(ys.iter().copied());
+        let h = b.push(u32::MAX);
+        b.decrease_key(h, 0);
+        a.meld(b);
+        assert_eq!(a.get(g), Some(&7));
+        assert_eq!(a.get(h), None);
+
+        let mut exp = xs.clone();
+        exp.sort();
+        if !exp.is_empty() {
+            exp.remove(0);
+        }
+        exp.extend(ys);
+        exp.extend([0, 7]);
+        exp.sort();
+        assert_eq!(std::iter::from_fn(|| a.pop()).collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    #[should_panic = "handle is stale"]
+    fn decrease_key_rejects_stale() {
+        let mut heap = HollowHeap::new();
+        let h = heap.push(3);
+        heap.pop();
+        heap.decrease_key(h, 1);
+    }
+}
@@ -0,0 +1,39 @@
+//! The structural invariants of the heaps.
+//!
+//! Both [`v1::FibonacciHeap::validate`] and [`v2::FibonacciHeap::validate`]
+//! walk the whole forest, so they cost `O(n)`. With the `debug-invariants`
+//! feature enabled, every mutating operation validates the heap afterwards
+//! and panics on a violation.
+//!
+//! [`v1::FibonacciHeap::validate`]: crate::v1::FibonacciHeap::validate
+//! [`v2::FibonacciHeap::validate`]: crate::v2::FibonacciHeap::validate
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// A child is less than its parent.
+    HeapOrder,
+    /// A root is less than the root tracked as the minimum.
+    MinNotMinimal,
+    /// The cached length does not match the number of nodes.
+    LenMismatch { cached: usize, counted: usize },
+    /// A tree has more children than `log2` of its size.
+    DegreeTooLarge { degree: usize, size: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeapOrder => write!(f, "child is less than its parent"),
+            Self::MinNotMinimal => write!(f, "a root is less than the minimum"),
+            Self::LenMismatch { cached, counted } => {
+                write!(f, "cached length {cached} does not match {counted} nodes")
+            }
+            Self::DegreeTooLarge { degree, size } => {
+                write!(f, "degree {degree} is too large for a tree of {size} nodes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
@@ -0,0 +1,539 @@
+//! Leftist heaps, binary trees kept heavy on the left so that every right
+//! spine has at most `log n` nodes.
+//!
+//! [`LeftistHeap::meld`] merges the two right spines, so it is `O(log n)` in
+//! the worst case, and `push` and `pop` are melds. The bounds don't depend on
+//! amortisation, so they hold for [`PersistentLeftistHeap`] too, where every
+//! version stays valid and shares all but the merged spine with the old ones.
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::Rc;
+
+pub struct LeftistHeap<T> {
+    root: Tree<T>,
+    len: usize,
+}
+
+type Tree<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    item: T,
+    // the length of the right spine
+    rank: usize,
+    left: Tree<T>,
+    right: Tree<T>,
+}
+
+impl<T: Ord> LeftistHeap<T> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|n| &n.item)
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.meld(Self {
+            root: Some(Box::new(Node::new(item))),
+            len: 1,
+        });
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let node = *self.root.take()?;
+        self.root = meld(node.left, node.right);
+        self.len -= 1;
+        Some(node.item)
+    }
+
+    /// Moves all the elements of `other` into `self`, in `O(log n)`.
+    pub fn meld(&mut self, mut other: Self) {
+        self.root = meld(self.root.take(), other.root.take());
+        self.len += other.len;
+    }
+
+    /// The elements in no particular order, though each after its parent.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            stack: self.root.as_deref().into_iter().collect(),
+            len: self.len,
+        }
+    }
+}
+
+fn meld<T: Ord>(mut a: Tree<T>, mut b: Tree<T>) -> Tree<T> {
+    // walk down the right spines, taking the lesser root each time
+    let mut spine = Vec::new();
+    let mut tree = loop {
+        match (a, b) {
+            (Some(x), Some(y)) => {
+                let (mut x, y) = if y.item < x.item { (y, x) } else { (x, y) };
+                a = x.right.take();
+                b = Some(y);
+                spine.push(x);
+            }
+            (rest, None) | (None, rest) => break rest,
+        }
+    };
+
+    // and back up, swapping children to keep the higher rank on the left
+    while let Some(mut x) = spine.pop() {
+        x.right = tree;
+        if rank(&x.left) < rank(&x.right) {
+            mem::swap(&mut x.left, &mut x.right);
+        }
+        x.rank = rank(&x.right) + 1;
+        tree = Some(x);
+    }
+    tree
+}
+
+fn rank<T>(tree: &Tree<T>) -> usize {
+    tree.as_ref().map_or(0, |n| n.rank)
+}
+
+impl<T> Node<T> {
+    fn new(item: T) -> Self {
+        Self {
+            item,
+            rank: 1,
+            left: None,
+            right: None,
+        }
+    }
+}
+
+impl<T> Drop for LeftistHeap<T> {
+    fn drop(&mut self) {
+        // take the tree apart iteratively, the left spine may be long
+        let mut stack = self.root.take().into_iter().collect::<Vec<_>>();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+impl<T: Ord> Default for LeftistHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds the heap in `O(n)`, melding the elements pairwise in rounds.
+impl<T: Ord> FromIterator<T> for LeftistHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut trees = iter
+            .into_iter()
+            .map(|x| Some(Box::new(Node::new(x))))
+            .collect::<VecDeque<_>>();
+        let len = trees.len();
+
+        while trees.len() > 1 {
+            let (a, b) = (trees.pop_front().unwrap(), trees.pop_front().unwrap());
+            trees.push_back(meld(a, b));
+        }
+
+        Self {
+            root: trees.pop_front().flatten(),
+            len,
+        }
+    }
+}
+
+impl<T: Ord> Extend<T> for LeftistHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.meld(Self::from_iter(iter));
+    }
+}
+
+/// Iterates over references to the elements, see [`LeftistHeap::iter`].
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.right.as_deref());
+        self.stack.extend(node.left.as_deref());
+        self.len -= 1;
+        Some(&node.item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: Ord> IntoIterator for &'a LeftistHeap<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Pops the elements in order.
+pub struct IntoIter<T>(LeftistHeap<T>);
+
+impl<T: Ord> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for IntoIter<T> {}
+
+impl<T: Ord> IntoIterator for LeftistHeap<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+/// A persistent leftist heap, whose operations return a new version and
+/// leave the old one valid.
+///
+/// Versions share their nodes through [`Rc`], so cloning a heap is `O(1)`,
+/// and a meld copies only the `O(log n)` nodes of the merged spines, cloning
+/// their elements.
+pub struct PersistentLeftistHeap<T> {
+    root: Shared<T>,
+    len: usize,
+}
+
+type Shared<T> = Option<Rc<SharedNode<T>>>;
+
+struct SharedNode<T> {
+    item: T,
+    rank: usize,
+    left: Shared<T>,
+    right: Shared<T>,
+}
+
+impl<T: Ord + Clone> PersistentLeftistHeap<T> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|n| &n.item)
+    }
+
+    /// Returns a new version with `item` added.
+    pub fn push(&self, item: T) -> Self {
+        let node = SharedNode {
+            item,
+            rank: 1,
+            left: None,
+            right: None,
+        };
+        Self {
+            root: meld_shared(&self.root, &Some(Rc::new(node))),
+            len: self.len + 1,
+        }
+    }
+
+    /// Returns the minimum and a new version without it.
+    ///
+    /// The minimum is cloned, since this version still holds it.
+    pub fn pop(&self) -> Option<(T, Self)> {
+        let root = self.root.as_ref()?;
+        let heap = Self {
+            root: meld_shared(&root.left, &root.right),
+            len: self.len - 1,
+        };
+        Some((root.item.clone(), heap))
+    }
+
+    /// Returns a new version holding the elements of both heaps.
+    pub fn meld(&self, other: &Self) -> Self {
+        Self {
+            root: meld_shared(&self.root, &other.root),
+            len: self.len + other.len,
+        }
+    }
+
+    /// The elements in no particular order, though each after its parent.
+    pub fn iter(&self) -> PersistentIter<'_, T> {
+        PersistentIter {
+            stack: self.root.as_deref().into_iter().collect(),
+            len: self.len,
+        }
+    }
+}
+
+fn meld_shared<T: Ord + Clone>(a: &Shared<T>, b: &Shared<T>) -> Shared<T> {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    // the copied spine, each node's element and left child
+    let mut spine = Vec::new();
+    let mut tree = loop {
+        match (a, b) {
+            (Some(x), Some(y)) => {
+                let (x, y) = if y.item < x.item { (y, x) } else { (x, y) };
+                a = x.right.clone();
+                b = Some(y);
+                spine.push((x.item.clone(), x.left.clone()));
+            }
+            (rest, None) | (None, rest) => break rest,
+        }
+    };
+
+    while let Some((item, mut left)) = spine.pop() {
+        let rank = |t: &Shared<T>| t.as_ref().map_or(0, |n| n.rank);
+        let mut right = tree;
+        if rank(&left) < rank(&right) {
+            mem::swap(&mut left, &mut right);
+        }
+        tree = Some(Rc::new(SharedNode {
+            item,
+            rank: rank(&right) + 1,
+            left,
+            right,
+        }));
+    }
+    tree
+}
+
+impl<T> Clone for PersistentLeftistHeap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Drop for PersistentLeftistHeap<T> {
+    fn drop(&mut self) {
+        // free the nodes only this version holds, iteratively
+        let mut stack = self.root.take().into_iter().collect::<Vec<_>>();
+        while let Some(rc) = stack.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(rc) {
+                stack.extend(node.left.take());
+                stack.extend(node.right.take());
+            }
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for PersistentLeftistHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for PersistentLeftistHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut trees = iter
+            .into_iter()
+            .map(|item| {
+                Some(Rc::new(SharedNode {
+                    item,
+                    rank: 1,
+                    left: None,
+                    right: None,
+                }))
+            })
+            .collect::<VecDeque<_>>();
+        let len = trees.len();
+
+        while trees.len() > 1 {
+            let (a, b) = (trees.pop_front().unwrap(), trees.pop_front().unwrap());
+            trees.push_back(meld_shared(&a, &b));
+        }
+
+        Self {
+            root: trees.pop_front().flatten(),
+            len,
+        }
+    }
+}
+
+/// Iterates over references to the elements, see
+/// [`PersistentLeftistHeap::iter`].
+pub struct PersistentIter<'a, T> {
+    stack: Vec<&'a SharedNode<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for PersistentIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.right.as_deref());
+        self.stack.extend(node.left.as_deref());
+        self.len -= 1;
+        Some(&node.item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for PersistentIter<'_, T> {}
+
+impl<'a, T: Ord + Clone> IntoIterator for &'a PersistentLeftistHeap<T> {
+    type Item = &'a T;
+    type IntoIter = PersistentIter<'a, T>;
+
+    fn into_iter(self) -> PersistentIter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    // checks heap order, ranks and the leftist property, returning the rank
+    fn verify(tree: &Tree<u32>) -> usize {
+        let Some(node) = tree else { return 0 };
+        for child in [&node.left, &node.right].into_iter().flatten() {
+            assert!(node.item <= child.item);
+        }
+        let (left, right) = (verify(&node.left), verify(&node.right));
+        assert!(left >= right);
+        assert_eq!(node.rank, right + 1);
+        node.rank
+    }
+
+    fn sorted(xs: &[u32]) -> Vec<u32> {
+        let mut xs = xs.to_vec();
+        xs.sort();
+        xs
+    }
+
+    fn drain<T: Ord + Clone>(mut heap: PersistentLeftistHeap<T>) -> Vec<T> {
+        let mut out = Vec::new();
+        while let Some((x, next)) = heap.pop() {
+            out.push(x);
+            heap = next;
+        }
+        out
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let heap = LeftistHeap::from_iter(xs.iter().copied());
+        verify(&heap.root);
+        assert_eq!(heap.len(), xs.len());
+        assert_eq!(heap.into_iter().collect::<Vec<_>>(), sorted(&xs));
+
+        let mut heap = LeftistHeap::new();
+        for &x in &xs {
+            heap.push(x);
+            verify(&heap.root);
+        }
+        assert_eq!(heap.into_iter().collect::<Vec<_>>(), sorted(&xs));
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = LeftistHeap::from_iter(xs.iter().copied());
+        a.extend(ys.iter().copied());
+        verify(&a.root);
+
+        let all = [xs, ys].concat();
+        assert_eq!(a.len(), all.len());
+        assert_eq!(a.into_iter().collect::<Vec<_>>(), sorted(&all));
+    }
+
+    #[quickcheck]
+    fn iter_visits_each_once(xs: Vec<u32>) {
+        let heap = LeftistHeap::from_iter(xs.iter().copied());
+        let seen = heap.iter().copied().collect::<Vec<_>>();
+        assert_eq!(heap.iter().len(), xs.len());
+        assert_eq!(seen.first(), heap.peek());
+        assert_eq!(sorted(&seen), sorted(&xs));
+
+        let heap = PersistentLeftistHeap::from_iter(xs.iter().copied());
+        let seen = heap.iter().copied().collect::<Vec<_>>();
+        assert_eq!(seen.first(), heap.peek());
+        assert_eq!(sorted(&seen), sorted(&xs));
+    }
+
+    #[quickcheck]
+    fn old_versions_are_unchanged(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut versions = vec![PersistentLeftistHeap::new()];
+        let mut model = vec![Vec::new()];
+
+        for (i, x) in xs.iter().enumerate() {
+            let mut heap = versions.last().unwrap().push(*x);
+            let mut m = model.last().unwrap().clone();
+            m.push(*x);
+
+            if i % 3 == 0 {
+                let (y, next) = heap.pop().unwrap();
+                m.sort();
+                assert_eq!(y, m.remove(0));
+                heap = next;
+            }
+
+            versions.push(heap);
+            model.push(m);
+        }
+
+        // meld a branch onto every version, without touching them
+        let branch = PersistentLeftistHeap::from_iter(ys.iter().copied());
+        for (heap, m) in versions.iter().zip(&model) {
+            let melded = heap.meld(&branch);
+            assert_eq!(drain(melded), sorted(&[m.clone(), ys.clone()].concat()));
+        }
+
+        for (heap, m) in versions.into_iter().zip(model) {
+            assert_eq!(heap.len(), m.len());
+            assert_eq!(drain(heap), sorted(&m));
+        }
+    }
+
+    #[test]
+    fn long_heaps_drop_without_overflow() {
+        // pushing in decreasing order leaves a single left spine
+        let mut heap = LeftistHeap::new();
+        let mut shared = PersistentLeftistHeap::new();
+        for x in (0..1_000_000u32).rev() {
+            heap.push(x);
+            shared = shared.push(x);
+        }
+        assert_eq!(heap.pop(), Some(0));
+        assert_eq!(shared.peek(), Some(&0));
+        drop(heap);
+        drop(shared);
+    }
+}
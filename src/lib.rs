@@ -1,2 +1,27 @@
+mod arena;
+pub mod async_queue;
+pub mod binomial;
+pub mod bucket;
+pub mod concurrent;
+pub mod dary;
+mod dot;
+pub mod durable;
+pub mod external;
+pub mod hollow;
+pub mod invariant;
+pub mod leftist;
+pub mod pairing;
+pub mod persistent;
+pub mod queue;
+pub mod radix;
+pub mod rank_pairing;
+pub mod skew;
+pub mod snapshot;
+pub mod soft;
+pub mod stats;
+pub mod strict;
+pub mod sync;
+pub mod timer;
+pub mod trace;
 pub mod v1;
 pub mod v2;
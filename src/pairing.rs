@@ -0,0 +1,360 @@
+//! A pairing heap, for comparison with the Fibonacci heaps.
+//!
+//! Nodes live in an arena and are addressed by [`Handle`]s, which
+//! [`PairingHeap::decrease_key`] uses to find them again. Each node keeps its
+//! leftmost child, its right sibling, and a back link to either its left
+//! sibling or (if leftmost) its parent.
+//!
+//! After the root is popped its children are combined according to a
+//! [`Strategy`]: two-pass is the classic choice with the better bounds,
+//! multipass tends to build shallower trees.
+use crate::arena::Arena;
+use std::collections::VecDeque;
+
+pub use crate::arena::Handle;
+
+/// How the children of a popped root are combined into one tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Link pairs left to right, then fold the pairs right to left.
+    #[default]
+    TwoPass,
+    /// Repeatedly link the first two trees, queueing the result at the back.
+    Multipass,
+}
+
+pub struct PairingHeap<T> {
+    nodes: Arena<Node<T>>,
+    root: Option<usize>,
+    len: usize,
+    strategy: Strategy,
+    // children of the popped root, kept to avoid allocating on every pop
+    scratch: VecDeque<usize>,
+}
+
+struct Node<T> {
+    item: T,
+    child: Option<usize>,
+    sibling: Option<usize>,
+    // the left sibling, or the parent of a leftmost child
+    prev: Option<usize>,
+}
+
+impl<T: Ord> PairingHeap<T> {
+    pub fn new() -> Self {
+        Self::with_strategy(Strategy::default())
+    }
+
+    pub fn with_strategy(strategy: Strategy) -> Self {
+        Self {
+            nodes: Arena::new(),
+            root: None,
+            len: 0,
+            strategy,
+            scratch: VecDeque::new(),
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.map(|r| &self.nodes[r].item)
+    }
+
+    /// The element `handle` refers to, or `None` if the handle is stale.
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.nodes.get(handle).map(|n| &n.item)
+    }
+
+    pub fn push(&mut self, item: T) -> Handle {
+        let node = Node {
+            item,
+            child: None,
+            sibling: None,
+            prev: None,
+        };
+
+        let handle = self.nodes.insert(node);
+        let index = handle.index;
+
+        self.root = Some(match self.root {
+            Some(r) => self.link(r, index),
+            None => index,
+        });
+        self.len += 1;
+
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let root = self.root?;
+
+        let node = self.nodes.remove(root);
+        self.len -= 1;
+
+        // detach the children
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let mut next = node.child;
+        while let Some(c) = next {
+            let child = &mut self.nodes[c];
+            next = child.sibling.take();
+            child.prev = None;
+            scratch.push_back(c);
+        }
+
+        self.root = match self.strategy {
+            Strategy::TwoPass => self.two_pass(&mut scratch),
+            Strategy::Multipass => self.multipass(&mut scratch),
+        };
+        self.scratch = scratch;
+
+        Some(node.item)
+    }
+
+    /// Lowers the element `handle` refers to down to `item`.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `item` is greater than the element.
+    pub fn decrease_key(&mut self, handle: Handle, item: T) {
+        assert!(self.get(handle).is_some(), "handle is stale");
+        let i = handle.index;
+
+        let node = &mut self.nodes[i];
+        assert!(item <= node.item, "decrease_key would increase the key");
+        node.item = item;
+
+        if self.root == Some(i) {
+            return;
+        }
+
+        // cut the subtree out of its sibling list
+        let node = &mut self.nodes[i];
+        let prev = node.prev.take().expect("non-root has a prev");
+        let sibling = node.sibling.take();
+
+        let before = &mut self.nodes[prev];
+        if before.child == Some(i) {
+            before.child = sibling;
+        } else {
+            before.sibling = sibling;
+        }
+        if let Some(s) = sibling {
+            self.nodes[s].prev = Some(prev);
+        }
+
+        let root = self.root.expect("not empty");
+        self.root = Some(self.link(root, i));
+    }
+
+    /// Moves all the elements of `other` into `self`.
+    ///
+    /// The arena of `other` is copied over, so this costs `O(other.len())`,
+    /// and handles into `other` go stale.
+    pub fn meld(&mut self, other: Self) {
+        let base = self.nodes.append(other.nodes, |n, base| {
+            let shift = |i: Option<usize>| i.map(|i| i + base);
+            Node {
+                child: shift(n.child),
+                sibling: shift(n.sibling),
+                prev: shift(n.prev),
+                ..n
+            }
+        });
+
+        self.len += other.len;
+        self.root = match (self.root, other.root.map(|r| r + base)) {
+            (Some(a), Some(b)) => Some(self.link(a, b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    fn two_pass(&mut self, trees: &mut VecDeque<usize>) -> Option<usize> {
+        // link pairs left to right, keeping the results in place
+        let pairs = trees.len() / 2;
+        for _ in 0..pairs {
+            let a = trees.pop_front().expect("pair");
+            let b = trees.pop_front().expect("pair");
+            let t = self.link(a, b);
+            trees.push_back(t);
+        }
+        // an odd tree out was left at the front, but belongs at the back
+        trees.rotate_left(trees.len() - pairs);
+
+        // fold right to left
+        let mut acc = trees.pop_back()?;
+        while let Some(t) = trees.pop_back() {
+            acc = self.link(t, acc);
+        }
+        Some(acc)
+    }
+
+    fn multipass(&mut self, trees: &mut VecDeque<usize>) -> Option<usize> {
+        while trees.len() > 1 {
+            let a = trees.pop_front().expect("two trees");
+            let b = trees.pop_front().expect("two trees");
+            let t = self.link(a, b);
+            trees.push_back(t);
+        }
+        trees.pop_front()
+    }
+
+    /// Links two detached trees, the greater root becoming the leftmost child
+    /// of the lesser. Returns the new root.
+    fn link(&mut self, a: usize, b: usize) -> usize {
+        let (parent, child) = if self.nodes[a].item <= self.nodes[b].item {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        let first = self.nodes[parent].child;
+        if let Some(f) = first {
+            self.nodes[f].prev = Some(child);
+        }
+
+        let c = &mut self.nodes[child];
+        c.sibling = first;
+        c.prev = Some(parent);
+        self.nodes[parent].child = Some(child);
+
+        parent
+    }
+}
+
+impl<T: Ord> Default for PairingHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for PairingHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Ord> Extend<T> for PairingHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        if let (_, Some(upr)) = iter.size_hint() {
+            self.nodes.reserve(upr);
+        }
+
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    const STRATEGIES: [Strategy; 2] = [Strategy::TwoPass, Strategy::Multipass];
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        for strategy in STRATEGIES {
+            let mut heap = PairingHeap::with_strategy(strategy);
+            heap.extend(xs.iter().copied());
+            assert_eq!(heap.len(), xs.len());
+
+            let mut xs = xs.clone();
+            xs.sort();
+            assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), xs);
+        }
+    }
+
+    #[quickcheck]
+    fn decrease_key_matches_model(ops: Vec<(u8, u32, usize)>) {
+        for strategy in STRATEGIES {
+            let mut heap = PairingHeap::with_strategy(strategy);
+            // live handles, with their current values
+            let mut live: Vec<(Handle, u32)> = Vec::new();
+
+            for &(op, x, i) in &ops {
+                match op % 4 {
+                    0 if !live.is_empty() => {
+                        let n = live.len();
+                        let (h, v) = &mut live[i % n];
+                        *v = x.min(*v);
+                        heap.decrease_key(*h, *v);
+                        assert_eq!(heap.get(*h), Some(&*v));
+                    }
+                    1 => {
+                        let min = live.iter().map(|(_, v)| *v).min();
+                        assert_eq!(heap.peek().copied(), min);
+
+                        let popped = heap.pop();
+                        assert_eq!(popped, min);
+                        // with duplicates, any handle of the value may go
+                        if popped.is_some() {
+                            let j = live.iter().position(|(h, _)| heap.get(*h).is_none());
+                            live.remove(j.unwrap());
+                        }
+                    }
+                    _ => live.push((heap.push(x), x)),
+                }
+                assert_eq!(heap.len(), live.len());
+            }
+
+            let mut rest = live.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+            rest.sort();
+            assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), rest);
+        }
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = PairingHeap::from_iter(xs.iter().copied());
+        a.pop();
+        let mut b = PairingHeap::from_iter(ys.iter().copied());
+        let h = b.push(0);
+        a.meld(b);
+        assert_eq!(a.get(h), None);
+
+        let mut exp = xs.clone();
+        exp.sort();
+        if !exp.is_empty() {
+            exp.remove(0);
+        }
+        exp.extend(ys);
+        exp.push(0);
+        exp.sort();
+        assert_eq!(std::iter::from_fn(|| a.pop()).collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    fn handles_go_stale() {
+        let mut heap = PairingHeap::new();
+        let a = heap.push(3);
+        assert_eq!(heap.pop(), Some(3));
+        assert_eq!(heap.get(a), None);
+
+        // the slot is reused, but the old handle still misses it
+        let b = heap.push(5);
+        assert_eq!(heap.get(a), None);
+        assert_eq!(heap.get(b), Some(&5));
+    }
+
+    #[test]
+    #[should_panic = "decrease_key would increase the key"]
+    fn decrease_key_rejects_increase() {
+        let mut heap = PairingHeap::new();
+        let h = heap.push(3);
+        heap.decrease_key(h, 4);
+    }
+}
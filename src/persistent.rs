@@ -0,0 +1,353 @@
+//! A persistent (immutable) Fibonacci heap.
+//!
+//! [`FibHeap::push`], [`FibHeap::pop`] and [`FibHeap::meld`] return a new
+//! version of the heap and leave the old one valid. Versions share their
+//! subtrees through [`Rc`], so cloning a heap is `O(1)` and a new version only
+//! allocates the nodes that changed.
+//!
+//! The amortised bounds of a Fibonacci heap assume every version is used
+//! once. Popping the same version repeatedly redoes its consolidation each
+//! time, costing up to `O(n)` per pop.
+//!
+//! Linking a shared node under another clones its element, so expensive
+//! elements are best wrapped in an [`Rc`] themselves.
+use crate::stats::Counters;
+use crate::v2::{order_min, rebalance, Link};
+use std::rc::Rc;
+
+pub struct FibHeap<T> {
+    // kept apart from the other roots so that peek is O(1)
+    min: Option<Tree<T>>,
+    roots: List<Tree<T>>,
+    len: usize,
+}
+
+impl<T: Ord + Clone> FibHeap<T> {
+    pub fn new() -> Self {
+        Self {
+            min: None,
+            roots: List::new(),
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.min.as_ref().map(|t| &t.0.item)
+    }
+
+    /// Returns a new version with `item` added.
+    pub fn push(&self, item: T) -> Self {
+        let tree = Tree::new(item);
+
+        let (min, roots) = match &self.min {
+            Some(min) if min.root() <= tree.root() => (min.clone(), self.roots.cons(tree)),
+            Some(min) => (tree, self.roots.cons(min.clone())),
+            None => (tree, List::new()),
+        };
+
+        Self {
+            min: Some(min),
+            roots,
+            len: self.len + 1,
+        }
+    }
+
+    /// Returns a new version holding the elements of both heaps.
+    ///
+    /// The shorter list of roots is copied onto the longer one, so this costs
+    /// `O(roots)` of whichever heap has fewer.
+    pub fn meld(&self, other: &Self) -> Self {
+        let (min, loser) = match (&self.min, &other.min) {
+            (_, None) => return self.clone(),
+            (None, _) => return other.clone(),
+            (Some(a), Some(b)) if a.root() <= b.root() => (a, b),
+            (Some(a), Some(b)) => (b, a),
+        };
+
+        // prepend the shorter list of roots onto the longer one
+        let (long, short) = if self.roots.len() >= other.roots.len() {
+            (&self.roots, &other.roots)
+        } else {
+            (&other.roots, &self.roots)
+        };
+
+        let roots = short
+            .iter()
+            .fold(long.clone(), |l, t| l.cons(t.clone()))
+            .cons(loser.clone());
+
+        Self {
+            min: Some(min.clone()),
+            roots,
+            len: self.len + other.len,
+        }
+    }
+
+    /// Returns the minimum and a new version without it.
+    ///
+    /// The minimum is cloned, since this version still holds it.
+    pub fn pop(&self) -> Option<(T, Self)> {
+        let min = self.min.clone()?;
+        let len = self.len - 1;
+
+        let (item, children) = min.into_parts();
+
+        // gather the forest to be consolidated
+        let mut roots = self.roots.iter().cloned().collect::<Vec<_>>();
+        roots.extend(children.iter().cloned());
+
+        let counters = &mut Counters::default();
+        rebalance(&mut roots, len, counters);
+        order_min(&mut roots, counters);
+
+        let min = roots.pop();
+        let roots = roots.into_iter().fold(List::new(), |l, t| l.cons(t));
+
+        Some((item, Self { min, roots, len }))
+    }
+}
+
+impl<T> Clone for FibHeap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            min: self.min.clone(),
+            roots: self.roots.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T: Ord + Clone> Default for FibHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for FibHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::new(), |h, x| h.push(x))
+    }
+}
+
+struct Tree<T>(Rc<Node<T>>);
+
+struct Node<T> {
+    item: T,
+    degree: usize,
+    children: List<Tree<T>>,
+}
+
+impl<T> Tree<T> {
+    fn new(item: T) -> Self {
+        Tree(Rc::new(Node {
+            item,
+            degree: 0,
+            children: List::new(),
+        }))
+    }
+}
+
+impl<T: Clone> Tree<T> {
+    /// Take the node apart, cloning the item only if it is shared.
+    fn into_parts(self) -> (T, List<Tree<T>>) {
+        match Rc::try_unwrap(self.0) {
+            Ok(mut node) => {
+                let children = std::mem::take(&mut node.children);
+                (node.item, children)
+            }
+            Err(rc) => (rc.item.clone(), rc.children.clone()),
+        }
+    }
+}
+
+impl<T> Clone for Tree<T> {
+    fn clone(&self) -> Self {
+        Tree(self.0.clone())
+    }
+}
+
+impl<T: Ord + Clone> Link for Tree<T> {
+    type Item = T;
+
+    fn root(&self) -> &T {
+        &self.0.item
+    }
+
+    fn degree(&self) -> usize {
+        self.0.degree
+    }
+
+    fn link(self, child: Self) -> Self {
+        let degree = self.0.degree + 1;
+        let (item, children) = self.into_parts();
+        Tree(Rc::new(Node {
+            item,
+            degree,
+            children: children.cons(child),
+        }))
+    }
+}
+
+/// A persistent singly-linked list.
+struct List<T>(Option<Rc<Cons<T>>>);
+
+struct Cons<T> {
+    head: T,
+    tail: List<T>,
+    // the length of the list from here on
+    len: usize,
+}
+
+impl<T> List<T> {
+    fn new() -> Self {
+        List(None)
+    }
+
+    fn cons(&self, head: T) -> Self {
+        List(Some(Rc::new(Cons {
+            head,
+            tail: self.clone(),
+            len: self.len() + 1,
+        })))
+    }
+
+    fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |cons| cons.len)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut next = self.0.as_deref();
+        std::iter::from_fn(move || {
+            let cons = next?;
+            next = cons.tail.0.as_deref();
+            Some(&cons.head)
+        })
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List(self.0.clone())
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // unlink iteratively, long lists would otherwise overflow the stack
+        let mut next = self.0.take();
+        while let Some(rc) = next {
+            match Rc::try_unwrap(rc) {
+                Ok(mut cons) => next = cons.tail.0.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    fn drain<T: Ord + Clone>(mut heap: FibHeap<T>) -> Vec<T> {
+        let mut out = Vec::new();
+        while let Some((x, next)) = heap.pop() {
+            out.push(x);
+            heap = next;
+        }
+        out
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let heap = xs.iter().copied().collect::<FibHeap<_>>();
+        assert_eq!(heap.len(), xs.len());
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(drain(heap), xs);
+    }
+
+    #[quickcheck]
+    fn old_versions_are_unchanged(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut versions = vec![FibHeap::new()];
+        let mut model = vec![Vec::new()];
+
+        // interleave pushes and pops, keeping every version
+        for (i, x) in xs.iter().enumerate() {
+            let mut heap = versions.last().unwrap().push(*x);
+            let mut m = model.last().unwrap().clone();
+            m.push(*x);
+
+            if i % 3 == 0 {
+                let (y, next) = heap.pop().unwrap();
+                m.sort();
+                assert_eq!(y, m.remove(0));
+                heap = next;
+            }
+
+            versions.push(heap);
+            model.push(m);
+        }
+
+        // branch off the final version, without touching it
+        let branch = ys
+            .iter()
+            .fold(versions.last().unwrap().clone(), |h, y| h.push(*y));
+        drain(branch);
+
+        for (heap, mut m) in versions.into_iter().zip(model) {
+            m.sort();
+            assert_eq!(heap.len(), m.len());
+            assert_eq!(drain(heap.clone()), m);
+            // draining twice gives the same result
+            assert_eq!(drain(heap), m);
+        }
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        let a = xs.iter().copied().collect::<FibHeap<_>>();
+        let b = ys.iter().copied().collect::<FibHeap<_>>();
+
+        // consolidate some of the trees first
+        let a = a.pop().map_or(a, |(x, a)| a.push(x));
+
+        let c = a.meld(&b);
+        assert_eq!(c.len(), xs.len() + ys.len());
+        if !a.is_empty() && !b.is_empty() {
+            // both lists, and the loser of the two minimums
+            assert_eq!(c.roots.len(), a.roots.len() + b.roots.len() + 1);
+        }
+
+        let mut all = [xs.clone(), ys.clone()].concat();
+        all.sort();
+        assert_eq!(drain(c), all);
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(drain(a), xs);
+    }
+
+    #[test]
+    fn long_heaps_drop_without_overflow() {
+        let heap = (0..1_000_000u32).collect::<FibHeap<_>>();
+        let (_, heap) = heap.pop().unwrap();
+        assert_eq!(heap.peek(), Some(&1));
+        drop(heap);
+    }
+}
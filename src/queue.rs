@@ -0,0 +1,294 @@
+//! A common interface over the heaps, so that algorithms can be written once
+//! and run on whichever backend suits the workload.
+//!
+//! Every queue is a min-queue. [`std::collections::BinaryHeap`] fits in as a
+//! queue of [`Reverse`] elements.
+use crate::binomial::BinomialHeap;
+use crate::bucket::{BucketQueue, CalendarQueue};
+use crate::dary::DaryHeap;
+use crate::hollow::HollowHeap;
+use crate::leftist::LeftistHeap;
+use crate::pairing::PairingHeap;
+use crate::rank_pairing::RankPairingHeap;
+use crate::skew::SkewHeap;
+use crate::strict::StrictFibonacciHeap;
+use crate::{v1, v2};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub trait PriorityQueue {
+    type Item;
+
+    fn push(&mut self, item: Self::Item);
+
+    /// Removes the least element.
+    fn pop(&mut self) -> Option<Self::Item>;
+
+    fn peek(&self) -> Option<&Self::Item>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A [`PriorityQueue`] whose elements can be lowered in place, through the
+/// handle they were pushed with.
+pub trait Addressable: PriorityQueue {
+    type Handle: Copy;
+
+    fn push_handle(&mut self, item: Self::Item) -> Self::Handle;
+
+    /// Lowers the element `handle` refers to down to `item`.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `item` is greater than the element.
+    fn decrease_key(&mut self, handle: Self::Handle, item: Self::Item);
+}
+
+macro_rules! heap {
+    ($($heap:ident)*) => {$(
+        impl<T: Ord> PriorityQueue for $heap<T> {
+            type Item = T;
+
+            fn push(&mut self, item: T) {
+                $heap::push(self, item);
+            }
+
+            fn pop(&mut self) -> Option<T> {
+                $heap::pop(self)
+            }
+
+            fn peek(&self) -> Option<&T> {
+                $heap::peek(self)
+            }
+
+            fn len(&self) -> usize {
+                $heap::len(self)
+            }
+        }
+    )*};
+}
+
+macro_rules! addressable {
+    ($($heap:ident)*) => {$(
+        heap!($heap);
+
+        impl<T: Ord> Addressable for $heap<T> {
+            type Handle = crate::arena::Handle;
+
+            fn push_handle(&mut self, item: T) -> Self::Handle {
+                $heap::push(self, item)
+            }
+
+            fn decrease_key(&mut self, handle: Self::Handle, item: T) {
+                $heap::decrease_key(self, handle, item)
+            }
+        }
+    )*};
+}
+
+// the macros need plain names
+type V1<T> = v1::FibonacciHeap<T>;
+type V2<T> = v2::FibonacciHeap<T>;
+
+heap!(V1 V2 BinomialHeap LeftistHeap SkewHeap);
+addressable!(PairingHeap RankPairingHeap StrictFibonacciHeap HollowHeap);
+
+impl<T: Ord> PriorityQueue for BinaryHeap<Reverse<T>> {
+    type Item = Reverse<T>;
+
+    fn push(&mut self, item: Reverse<T>) {
+        BinaryHeap::push(self, item);
+    }
+
+    fn pop(&mut self) -> Option<Reverse<T>> {
+        BinaryHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&Reverse<T>> {
+        BinaryHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        BinaryHeap::len(self)
+    }
+}
+
+impl<T: Ord, const D: usize> PriorityQueue for DaryHeap<T, D> {
+    type Item = T;
+
+    fn push(&mut self, item: T) {
+        DaryHeap::push(self, item);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        DaryHeap::pop(self)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        DaryHeap::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        DaryHeap::len(self)
+    }
+}
+
+impl<T: Ord, const D: usize> Addressable for DaryHeap<T, D> {
+    type Handle = crate::arena::Handle;
+
+    fn push_handle(&mut self, item: T) -> Self::Handle {
+        DaryHeap::push(self, item)
+    }
+
+    fn decrease_key(&mut self, handle: Self::Handle, item: T) {
+        DaryHeap::decrease_key(self, handle, item)
+    }
+}
+
+impl<V> PriorityQueue for BucketQueue<V> {
+    type Item = (usize, V);
+
+    fn push(&mut self, (priority, value): (usize, V)) {
+        BucketQueue::push(self, priority, value);
+    }
+
+    fn pop(&mut self) -> Option<(usize, V)> {
+        BucketQueue::pop(self)
+    }
+
+    fn peek(&self) -> Option<&(usize, V)> {
+        BucketQueue::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        BucketQueue::len(self)
+    }
+}
+
+impl<V> Addressable for BucketQueue<V> {
+    type Handle = crate::arena::Handle;
+
+    fn push_handle(&mut self, (priority, value): (usize, V)) -> Self::Handle {
+        BucketQueue::push(self, priority, value)
+    }
+
+    fn decrease_key(&mut self, handle: Self::Handle, (priority, value): (usize, V)) {
+        BucketQueue::decrease_key(self, handle, priority);
+        *BucketQueue::get_mut(self, handle).expect("just lowered") = value;
+    }
+}
+
+impl<V> PriorityQueue for CalendarQueue<V> {
+    type Item = (u64, V);
+
+    fn push(&mut self, (time, value): (u64, V)) {
+        CalendarQueue::push(self, time, value);
+    }
+
+    fn pop(&mut self) -> Option<(u64, V)> {
+        CalendarQueue::pop(self)
+    }
+
+    fn peek(&self) -> Option<&(u64, V)> {
+        CalendarQueue::peek(self)
+    }
+
+    fn len(&self) -> usize {
+        CalendarQueue::len(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    fn drain<Q: PriorityQueue>(mut queue: Q, items: Vec<Q::Item>) -> Vec<Q::Item> {
+        for x in items {
+            queue.push(x);
+        }
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[quickcheck]
+    fn backends_agree(xs: Vec<(u8, u16)>) {
+        let items = xs.iter().map(|&(p, v)| (p as usize, v)).collect::<Vec<_>>();
+        let exp = drain(
+            BinaryHeap::new(),
+            items.iter().copied().map(Reverse).collect(),
+        )
+        .into_iter()
+        .map(|Reverse(x)| x)
+        .collect::<Vec<_>>();
+
+        assert_eq!(drain(V1::new(), items.clone()), exp);
+        assert_eq!(drain(V2::new(), items.clone()), exp);
+        assert_eq!(drain(BinomialHeap::new(), items.clone()), exp);
+        assert_eq!(drain(LeftistHeap::new(), items.clone()), exp);
+        assert_eq!(drain(SkewHeap::new(), items.clone()), exp);
+        assert_eq!(drain(PairingHeap::new(), items.clone()), exp);
+        assert_eq!(drain(RankPairingHeap::new(), items.clone()), exp);
+        assert_eq!(drain(StrictFibonacciHeap::new(), items.clone()), exp);
+        assert_eq!(drain(HollowHeap::new(), items.clone()), exp);
+        assert_eq!(drain(DaryHeap::<_, 4>::new(), items.clone()), exp);
+
+        // these keep equal priorities in push order, so compare priorities
+        let priorities = |xs: Vec<(usize, u16)>| xs.into_iter().map(|x| x.0).collect::<Vec<_>>();
+        let exp = priorities(exp);
+        let bucket = drain(BucketQueue::new(256), items.clone());
+        assert_eq!(priorities(bucket), exp);
+        let times = items.iter().map(|&(p, v)| (p as u64, v)).collect();
+        let calendar = drain(CalendarQueue::new(), times);
+        assert_eq!(
+            calendar
+                .into_iter()
+                .map(|x| x.0 as usize)
+                .collect::<Vec<_>>(),
+            exp
+        );
+    }
+
+    // shortest distances from node 0, written once for any backend
+    fn dijkstra<Q>(mut queue: Q, graph: &[Vec<(usize, usize)>]) -> Vec<usize>
+    where
+        Q: Addressable<Item = (usize, usize)>,
+    {
+        let mut dist = vec![usize::MAX; graph.len()];
+        let mut handles = vec![None; graph.len()];
+        dist[0] = 0;
+        queue.push_handle((0, 0));
+
+        while let Some((d, u)) = queue.pop() {
+            for &(v, w) in &graph[u] {
+                if d + w < dist[v] {
+                    dist[v] = d + w;
+                    match handles[v] {
+                        Some(h) => queue.decrease_key(h, (d + w, v)),
+                        None => handles[v] = Some(queue.push_handle((d + w, v))),
+                    }
+                }
+            }
+        }
+
+        dist
+    }
+
+    #[quickcheck]
+    fn addressable_backends_agree(edges: Vec<(u8, u8, u8)>) {
+        let mut graph = vec![Vec::new(); 16];
+        for (u, v, w) in edges {
+            graph[u as usize % 16].push((v as usize % 16, w as usize % 16));
+        }
+
+        let exp = dijkstra(PairingHeap::new(), &graph);
+        assert_eq!(dijkstra(RankPairingHeap::new(), &graph), exp);
+        assert_eq!(dijkstra(StrictFibonacciHeap::new(), &graph), exp);
+        assert_eq!(dijkstra(HollowHeap::new(), &graph), exp);
+        assert_eq!(dijkstra(DaryHeap::<_, 4>::new(), &graph), exp);
+        // 15 hops of at most 15 each
+        assert_eq!(dijkstra(BucketQueue::new(256), &graph), exp);
+    }
+}
@@ -0,0 +1,220 @@
+//! A monotone radix heap, for integer-like keys which never go back below
+//! the last one popped, as in Dijkstra's algorithm with non-negative weights.
+//!
+//! Elements are kept in buckets by the highest bit in which their key
+//! differs from the last popped key. Popping empties the lowest bucket down
+//! into the ones below it, and each element can only move down as many times
+//! as the key has bits. So `push` is `O(1)` and `pop` is `O(log C)`
+//! amortised, for keys of `log C` bits, with no comparisons between elements
+//! at all.
+use std::fmt;
+
+/// A key a [`RadixHeap`] can order by its bits.
+pub trait RadixKey: Copy {
+    /// The key as an unsigned integer, in the same order as the keys.
+    fn to_radix(self) -> u64;
+}
+
+macro_rules! unsigned {
+    ($($t:ty)*) => {$(
+        impl RadixKey for $t {
+            fn to_radix(self) -> u64 {
+                self as u64
+            }
+        }
+    )*};
+}
+
+unsigned!(u8 u16 u32 u64 usize);
+
+/// Floats are ordered as by [`f32::total_cmp`], so `-0.0` comes before `0.0`
+/// and NaNs sort to the ends.
+impl RadixKey for f32 {
+    fn to_radix(self) -> u64 {
+        let bits = self.to_bits();
+        // flip every bit of a negative, and just the sign bit otherwise
+        let mask = ((bits as i32 >> 31) as u32) | (1 << 31);
+        (bits ^ mask) as u64
+    }
+}
+
+/// Ordered as by [`f64::total_cmp`], like `f32`.
+impl RadixKey for f64 {
+    fn to_radix(self) -> u64 {
+        let bits = self.to_bits();
+        let mask = ((bits as i64 >> 63) as u64) | (1 << 63);
+        bits ^ mask
+    }
+}
+
+/// Returned by [`RadixHeap::push`] when the key is below the last one
+/// popped, giving back the key and value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushError<K, V>(pub K, pub V);
+
+impl<K, V> fmt::Display for PushError<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key is below the last popped key")
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> std::error::Error for PushError<K, V> {}
+
+pub struct RadixHeap<K, V> {
+    // buckets[b] holds the keys whose highest bit differing from `last` is
+    // bit b - 1, and buckets[0] those equal to it
+    buckets: Vec<Vec<(K, V)>>,
+    last: u64,
+    len: usize,
+}
+
+impl<K: RadixKey, V> RadixHeap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..=u64::BITS).map(|_| Vec::new()).collect(),
+            last: 0,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The least key, found by a scan of the lowest bucket.
+    pub fn peek_key(&self) -> Option<K> {
+        let bucket = self.buckets.iter().find(|b| !b.is_empty())?;
+        bucket.iter().map(|&(k, _)| k).min_by_key(|k| k.to_radix())
+    }
+
+    /// Pushes an element, unless its key is below the last one popped.
+    pub fn push(&mut self, key: K, value: V) -> Result<(), PushError<K, V>> {
+        let radix = key.to_radix();
+        if radix < self.last {
+            return Err(PushError(key, value));
+        }
+
+        self.buckets[bucket(radix, self.last)].push((key, value));
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        if self.buckets[0].is_empty() {
+            let b = self.buckets.iter().position(|b| !b.is_empty())?;
+
+            // the new last key is the least in the bucket, and the rest of
+            // it now differ from it in lower bits
+            let mut elems = std::mem::take(&mut self.buckets[b]);
+            self.last = elems.iter().map(|(k, _)| k.to_radix()).min().unwrap();
+            for (k, v) in elems.drain(..) {
+                self.buckets[bucket(k.to_radix(), self.last)].push((k, v));
+            }
+            // keep the allocation
+            self.buckets[b] = elems;
+        }
+
+        self.len -= 1;
+        self.buckets[0].pop()
+    }
+}
+
+fn bucket(radix: u64, last: u64) -> usize {
+    (u64::BITS - (radix ^ last).leading_zeros()) as usize
+}
+
+impl<K: RadixKey, V> Default for RadixHeap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    fn drain<K: RadixKey, V>(heap: &mut RadixHeap<K, V>) -> Vec<K> {
+        std::iter::from_fn(|| heap.pop()).map(|(k, _)| k).collect()
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let mut heap = RadixHeap::new();
+        for &x in &xs {
+            heap.push(x, ()).unwrap();
+        }
+        assert_eq!(heap.len(), xs.len());
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(drain(&mut heap), xs);
+    }
+
+    #[quickcheck]
+    fn floats_pop_in_total_order(xs: Vec<f64>) {
+        let mut heap = RadixHeap::new();
+        for &x in &xs {
+            heap.push(x, ()).unwrap();
+        }
+
+        let mut xs = xs;
+        xs.sort_by(f64::total_cmp);
+        let bits = |xs: Vec<f64>| xs.into_iter().map(f64::to_bits).collect::<Vec<_>>();
+        assert_eq!(bits(drain(&mut heap)), bits(xs));
+    }
+
+    #[test]
+    fn float_keys_keep_their_order() {
+        let xs = [
+            f32::NEG_INFINITY,
+            -2.5,
+            -1.0,
+            -0.0,
+            0.0,
+            1e-30,
+            1.0,
+            3.5,
+            f32::INFINITY,
+        ];
+        assert!(xs.windows(2).all(|w| w[0].to_radix() < w[1].to_radix()));
+    }
+
+    #[quickcheck]
+    fn monotone_pushes_match_model(ops: Vec<(bool, u16)>) {
+        let mut heap = RadixHeap::new();
+        let mut model = std::collections::BinaryHeap::new();
+        let mut last = 0u64;
+
+        for (pop, d) in ops {
+            if pop {
+                let popped = heap.pop().map(|(k, _)| k);
+                assert_eq!(popped, model.pop().map(|std::cmp::Reverse(k)| k));
+                last = popped.unwrap_or(last);
+            } else {
+                // never below the last popped key
+                heap.push(last + d as u64, ()).unwrap();
+                model.push(std::cmp::Reverse(last + d as u64));
+            }
+
+            assert_eq!(heap.len(), model.len());
+            assert_eq!(heap.peek_key(), model.peek().map(|r| r.0));
+        }
+    }
+
+    #[test]
+    fn rejects_keys_below_the_last_pop() {
+        let mut heap = RadixHeap::new();
+        heap.push(5u32, 'a').unwrap();
+        heap.push(9, 'b').unwrap();
+        assert_eq!(heap.pop(), Some((5, 'a')));
+
+        assert_eq!(heap.push(4, 'c'), Err(PushError(4, 'c')));
+        assert_eq!(heap.push(5, 'd'), Ok(()));
+        assert_eq!(drain(&mut heap), [5, 9]);
+    }
+}
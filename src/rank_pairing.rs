@@ -0,0 +1,398 @@
+//! A type-2 rank-pairing heap, after Haeupler, Sen and Tarjan.
+//!
+//! The heap is a list of _half-trees_: binary trees whose roots have no right
+//! child, where every node is no greater than the nodes in its left subtree.
+//! Linking two half-trees of equal rank makes the greater root the left child
+//! of the lesser, taking the old left subtree as its right.
+//!
+//! [`RankPairingHeap::decrease_key`] cuts a node away with its left subtree
+//! and then only lowers ranks on the way up, rather than cascading cuts.
+//! Popping links roots of equal rank in a single pass. This gives the
+//! amortised bounds of a Fibonacci heap: `O(1)` for everything but `pop`,
+//! which is `O(log n)`.
+use crate::arena::Arena;
+
+pub use crate::arena::Handle;
+
+pub struct RankPairingHeap<T> {
+    nodes: Arena<Node<T>>,
+    roots: Vec<usize>,
+    min: Option<usize>,
+    len: usize,
+    // roots by rank while popping, kept to avoid allocating on every pop
+    buckets: Vec<Option<usize>>,
+}
+
+struct Node<T> {
+    item: T,
+    rank: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+    parent: Option<usize>,
+}
+
+impl<T: Ord> RankPairingHeap<T> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Arena::new(),
+            roots: Vec::new(),
+            min: None,
+            len: 0,
+            buckets: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.min.map(|m| &self.nodes[m].item)
+    }
+
+    /// The element `handle` refers to, or `None` if the handle is stale.
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.nodes.get(handle).map(|n| &n.item)
+    }
+
+    pub fn push(&mut self, item: T) -> Handle {
+        let handle = self.nodes.insert(Node {
+            item,
+            rank: 0,
+            left: None,
+            right: None,
+            parent: None,
+        });
+
+        self.add_root(handle.index);
+        self.len += 1;
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let min = self.min.take()?;
+        let node = self.nodes.remove(min);
+        self.len -= 1;
+
+        let mut trees = std::mem::take(&mut self.roots);
+        trees.retain(|&r| r != min);
+
+        // the right spine of the left child falls apart into half-trees
+        let mut next = node.left;
+        while let Some(c) = next {
+            let rank = self.rank(self.nodes[c].left) + 1;
+            let child = &mut self.nodes[c];
+            next = child.right.take();
+            child.parent = None;
+            child.rank = rank as usize;
+            trees.push(c);
+        }
+
+        // one pass: link each pair of equal rank as it is found, without
+        // linking the results any further
+        let mut buckets = std::mem::take(&mut self.buckets);
+        for &t in &trees {
+            let r = self.nodes[t].rank;
+            if buckets.len() <= r {
+                buckets.resize(r + 1, None);
+            }
+            match buckets[r].take() {
+                Some(u) => {
+                    let root = self.link(u, t);
+                    self.roots.push(root);
+                }
+                None => buckets[r] = Some(t),
+            }
+        }
+        self.roots
+            .extend(buckets.iter_mut().filter_map(Option::take));
+        self.buckets = buckets;
+
+        self.min = self
+            .roots
+            .iter()
+            .copied()
+            .min_by(|&a, &b| self.nodes[a].item.cmp(&self.nodes[b].item));
+
+        Some(node.item)
+    }
+
+    /// Lowers the element `handle` refers to down to `item`.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `item` is greater than the element.
+    pub fn decrease_key(&mut self, handle: Handle, item: T) {
+        assert!(self.get(handle).is_some(), "handle is stale");
+        let x = handle.index;
+
+        let node = &mut self.nodes[x];
+        assert!(item <= node.item, "decrease_key would increase the key");
+        node.item = item;
+
+        let Some(parent) = node.parent.take() else {
+            // already a root, it may just be the new minimum
+            self.update_min(x);
+            return;
+        };
+
+        // cut x with its left subtree, its right subtree takes its place
+        let right = node.right.take();
+        let p = &mut self.nodes[parent];
+        if p.left == Some(x) {
+            p.left = right;
+        } else {
+            p.right = right;
+        }
+        if let Some(r) = right {
+            self.nodes[r].parent = Some(parent);
+        }
+
+        self.nodes[x].rank = (self.rank(self.nodes[x].left) + 1) as usize;
+        self.roots.push(x);
+        self.update_min(x);
+
+        // lower the ranks above, stopping as soon as one is unchanged
+        let mut u = parent;
+        loop {
+            let left = self.rank(self.nodes[u].left);
+            let Some(up) = self.nodes[u].parent else {
+                self.nodes[u].rank = (left + 1) as usize;
+                break;
+            };
+
+            let right = self.rank(self.nodes[u].right);
+            let k = if left.abs_diff(right) > 1 {
+                left.max(right)
+            } else {
+                left.max(right) + 1
+            };
+            if k >= self.nodes[u].rank as isize {
+                break;
+            }
+
+            self.nodes[u].rank = k as usize;
+            u = up;
+        }
+    }
+
+    /// Moves all the elements of `other` into `self`.
+    ///
+    /// The nodes of `other` are copied over, so this costs `O(other.len())`,
+    /// and handles into `other` go stale.
+    pub fn meld(&mut self, other: Self) {
+        let base = self.nodes.append(other.nodes, |n, base| {
+            let shift = |i: Option<usize>| i.map(|i| i + base);
+            Node {
+                left: shift(n.left),
+                right: shift(n.right),
+                parent: shift(n.parent),
+                ..n
+            }
+        });
+
+        self.len += other.len;
+        self.roots.extend(other.roots.iter().map(|r| r + base));
+        if let Some(m) = other.min {
+            self.update_min(m + base);
+        }
+    }
+
+    fn add_root(&mut self, x: usize) {
+        self.roots.push(x);
+        self.update_min(x);
+    }
+
+    fn update_min(&mut self, x: usize) {
+        match self.min {
+            Some(m) if self.nodes[m].item <= self.nodes[x].item => (),
+            _ => self.min = Some(x),
+        }
+    }
+
+    /// The rank of a node, where a missing child has rank -1.
+    fn rank(&self, i: Option<usize>) -> isize {
+        i.map_or(-1, |i| self.nodes[i].rank as isize)
+    }
+
+    /// Links two half-trees of equal rank, returning the new root.
+    fn link(&mut self, a: usize, b: usize) -> usize {
+        let (x, y) = if self.nodes[a].item <= self.nodes[b].item {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        let left = self.nodes[x].left;
+        if let Some(l) = left {
+            self.nodes[l].parent = Some(y);
+        }
+
+        let child = &mut self.nodes[y];
+        child.right = left;
+        child.parent = Some(x);
+
+        let root = &mut self.nodes[x];
+        root.left = Some(y);
+        root.rank += 1;
+
+        x
+    }
+}
+
+impl<T: Ord> Default for RankPairingHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for RankPairingHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Ord> Extend<T> for RankPairingHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        if let (_, Some(upr)) = iter.size_hint() {
+            self.nodes.reserve(upr);
+        }
+
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    // checks the half-ordering and the type-2 rank rule, returning the
+    // minimum of the subtree
+    fn verify(heap: &RankPairingHeap<u32>, i: usize) -> u32 {
+        let node = &heap.nodes[i];
+        let (l, r) = (heap.rank(node.left), heap.rank(node.right));
+        let rank = node.rank as isize;
+
+        let mut min = node.item;
+        if let Some(left) = node.left {
+            assert_eq!(heap.nodes[left].parent, Some(i));
+            assert!(node.item <= verify(heap, left), "half-ordered");
+        }
+        if let Some(right) = node.right {
+            assert_eq!(heap.nodes[right].parent, Some(i));
+            min = min.min(verify(heap, right));
+        }
+
+        if node.parent.is_none() {
+            assert_eq!(node.right, None, "roots are half-trees");
+            assert_eq!(rank, l + 1);
+        } else {
+            let diffs = (rank - l, rank - r);
+            let ok = matches!(diffs, (1, 1) | (1, 2) | (2, 1) | (0, 1..) | (1.., 0));
+            assert!(ok, "rank differences {diffs:?}");
+        }
+
+        min
+    }
+
+    fn verify_all(heap: &RankPairingHeap<u32>) {
+        let mins = heap.roots.iter().map(|&r| verify(heap, r));
+        assert_eq!(mins.min(), heap.peek().copied());
+    }
+
+    #[quickcheck]
+    fn pops_in_order(xs: Vec<u32>) {
+        let mut heap = RankPairingHeap::from_iter(xs.iter().copied());
+        assert_eq!(heap.len(), xs.len());
+
+        let mut xs = xs;
+        xs.sort();
+        for x in xs {
+            assert_eq!(heap.pop(), Some(x));
+            verify_all(&heap);
+        }
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[quickcheck]
+    fn decrease_key_matches_model(ops: Vec<(u8, u32, usize)>) {
+        let mut heap = RankPairingHeap::new();
+        // live handles, with their current values
+        let mut live: Vec<(Handle, u32)> = Vec::new();
+
+        for &(op, x, i) in &ops {
+            match op % 4 {
+                0 | 1 if !live.is_empty() => {
+                    let n = live.len();
+                    let (h, v) = &mut live[i % n];
+                    *v = x.min(*v);
+                    heap.decrease_key(*h, *v);
+                    assert_eq!(heap.get(*h), Some(&*v));
+                }
+                2 => {
+                    let min = live.iter().map(|(_, v)| *v).min();
+                    assert_eq!(heap.pop(), min);
+                    // with duplicates, any handle of the value may go
+                    if min.is_some() {
+                        let j = live.iter().position(|(h, _)| heap.get(*h).is_none());
+                        live.remove(j.unwrap());
+                    }
+                }
+                _ => live.push((heap.push(x), x)),
+            }
+
+            assert_eq!(heap.len(), live.len());
+            verify_all(&heap);
+        }
+
+        let mut rest = live.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        rest.sort();
+        assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), rest);
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = RankPairingHeap::from_iter(xs.iter().copied());
+        a.pop();
+        let mut b = RankPairingHeap::from_iter(ys.iter().copied());
+        b.pop();
+        let h = b.push(7);
+
+        a.meld(b);
+        verify_all(&a);
+        assert_eq!(a.get(h), None);
+
+        let mut exp = xs.clone();
+        exp.sort();
+        if !exp.is_empty() {
+            exp.remove(0);
+        }
+        let mut ys = ys;
+        ys.sort();
+        if !ys.is_empty() {
+            ys.remove(0);
+        }
+        exp.extend(ys);
+        exp.push(7);
+        exp.sort();
+        assert_eq!(std::iter::from_fn(|| a.pop()).collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    #[should_panic = "handle is stale"]
+    fn decrease_key_rejects_stale() {
+        let mut heap = RankPairingHeap::new();
+        let h = heap.push(3);
+        heap.pop();
+        heap.decrease_key(h, 1);
+    }
+}
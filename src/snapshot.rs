@@ -0,0 +1,508 @@
+//! A compact, versioned binary snapshot format for the heaps.
+//!
+//! All integers are little-endian. A snapshot is laid out as:
+//!
+//! | field        | encoding                                   |
+//! |--------------|--------------------------------------------|
+//! | magic        | `b"FIBHEAP\0"`                             |
+//! | version      | `u16`, currently [`VERSION`]               |
+//! | element count| `u64`                                      |
+//! | root count   | `u64`                                      |
+//! | trees        | pre-order: `u32` degree, then the payload  |
+//! | checksum     | `u32` CRC-32 (IEEE) of all preceding bytes |
+//!
+//! The minimum root is always written first, so a snapshot taken from
+//! [`v1::FibonacciHeap`](crate::v1::FibonacciHeap) loads into
+//! [`v2::FibonacciHeap`](crate::v2::FibonacciHeap) and vice versa. Payloads are
+//! encoded with the [`Element`] trait.
+use std::cmp::Reverse;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: [u8; 8] = *b"FIBHEAP\0";
+pub const VERSION: u16 = 1;
+
+// trees built by linking are at most log2(n) deep, anything deeper than the
+// bits in a u64 is malformed (and would risk overflowing the stack)
+const MAX_DEPTH: usize = 64;
+
+/// An element which can be written to, and read from, a snapshot.
+pub trait Element: Sized {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! impl_element_int {
+    ($($t:ty),*) => {$(
+        impl Element for $t {
+            fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+
+            fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+                let mut buf = [0; std::mem::size_of::<$t>()];
+                r.read_exact(&mut buf)?;
+                Ok(<$t>::from_le_bytes(buf))
+            }
+        }
+    )*};
+}
+
+impl_element_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Element for bool {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match u8::decode(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("bool is not 0 or 1")),
+        }
+    }
+}
+
+impl Element for String {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.len() as u64).encode(w)?;
+        w.write_all(self.as_bytes())
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let len = u64::decode(r)?;
+        let mut buf = Vec::new();
+        // take avoids allocating a bogus length up front
+        r.take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(buf).map_err(|_| invalid("string is not utf-8"))
+    }
+}
+
+impl<T: Element> Element for Reverse<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        T::decode(r).map(Reverse)
+    }
+}
+
+impl<A: Element, B: Element> Element for (A, B) {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)?;
+        self.1.encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok((A::decode(r)?, B::decode(r)?))
+    }
+}
+
+impl<A: Element, B: Element, C: Element> Element for (A, B, C) {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)?;
+        self.1.encode(w)?;
+        self.2.encode(w)
+    }
+
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok((A::decode(r)?, B::decode(r)?, C::decode(r)?))
+    }
+}
+
+/// Writes a forest of `len` nodes. `roots` must yield the minimum root first.
+pub(crate) fn write_forest<'a, T, N, W>(
+    w: &mut W,
+    len: usize,
+    roots: &[&'a N],
+    view: fn(&'a N) -> (&'a T, &'a [N]),
+) -> io::Result<()>
+where
+    T: Element + 'a,
+    W: Write,
+{
+    let mut w = Crc32Writer::new(w);
+
+    w.write_all(&MAGIC)?;
+    VERSION.encode(&mut w)?;
+    (len as u64).encode(&mut w)?;
+    (roots.len() as u64).encode(&mut w)?;
+
+    fn write_tree<'a, T: Element + 'a, N, W: Write>(
+        w: &mut W,
+        tree: &'a N,
+        view: fn(&'a N) -> (&'a T, &'a [N]),
+    ) -> io::Result<()> {
+        let (node, children) = view(tree);
+        (children.len() as u32).encode(w)?;
+        node.encode(w)?;
+        children.iter().try_for_each(|c| write_tree(w, c, view))
+    }
+
+    for tree in roots {
+        write_tree(&mut w, *tree, view)?;
+    }
+
+    let crc = w.crc();
+    crc.encode(w.inner)
+}
+
+/// Reads a forest, returning the number of nodes and the roots (minimum root
+/// first).
+///
+/// Only the framing is validated here, the heap invariants are left to the
+/// caller.
+pub(crate) fn read_forest<T, N, R>(r: R, build: fn(T, Vec<N>) -> N) -> io::Result<(usize, Vec<N>)>
+where
+    T: Element,
+    R: Read,
+{
+    let mut r = Crc32Reader::new(r);
+
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a heap snapshot"));
+    }
+
+    let version = u16::decode(&mut r)?;
+    if version != VERSION {
+        return Err(invalid("unsupported snapshot version"));
+    }
+
+    let len = u64::decode(&mut r)?;
+    let nroots = u64::decode(&mut r)?;
+    if nroots > len {
+        return Err(invalid("more roots than elements"));
+    }
+
+    // counts down the nodes which are yet to be read
+    let mut remaining = len;
+
+    fn read_tree<T: Element, N, R: Read>(
+        r: &mut R,
+        remaining: &mut u64,
+        depth: usize,
+        build: fn(T, Vec<N>) -> N,
+    ) -> io::Result<N> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("tree is too deep"));
+        }
+        *remaining = remaining
+            .checked_sub(1)
+            .ok_or_else(|| invalid("more nodes than elements"))?;
+
+        let degree = u32::decode(r)? as u64;
+        if degree > *remaining {
+            return Err(invalid("more children than elements"));
+        }
+
+        let node = T::decode(r)?;
+        let children = (0..degree)
+            .map(|_| read_tree(r, remaining, depth + 1, build))
+            .collect::<io::Result<_>>()?;
+
+        Ok(build(node, children))
+    }
+
+    let roots = (0..nroots)
+        .map(|_| read_tree(&mut r, &mut remaining, 0, build))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    if remaining != 0 {
+        return Err(invalid("fewer nodes than elements"));
+    }
+
+    let crc = r.crc();
+    if u32::decode(&mut r.inner)? != crc {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    let len = usize::try_from(len).map_err(|_| invalid("too many elements"))?;
+    Ok((len, roots))
+}
+
+pub(crate) fn invalid<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32_update(crc: u32, buf: &[u8]) -> u32 {
+    !buf.iter().fold(!crc, |c, &b| {
+        CRC32_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+struct Crc32Writer<W> {
+    inner: W,
+    crc: u32,
+}
+
+impl<W> Crc32Writer<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, crc: 0 }
+    }
+
+    fn crc(&self) -> u32 {
+        self.crc
+    }
+}
+
+impl<W: Write> Write for Crc32Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct Crc32Reader<R> {
+    inner: R,
+    crc: u32,
+}
+
+impl<R> Crc32Reader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, crc: 0 }
+    }
+
+    fn crc(&self) -> u32 {
+        self.crc
+    }
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{v1, v2};
+    use quickcheck_macros::*;
+
+    // a fixed workload giving a non-trivial forest shape
+    fn golden_heap<H: FromIterator<u32>>() -> H {
+        (0..20u32).map(|x| (x * 7919) % 31).collect()
+    }
+
+    // written by v2 from `golden_heap` with its minimum popped; only ever
+    // decoded, as the bytes written depend on how the heap links its trees
+    const GOLDEN: &[u8] = include_bytes!("../tests/golden/v2-u32.bin");
+
+    fn golden_elements() -> Vec<u32> {
+        let mut xs: Vec<u32> = golden_heap();
+        xs.sort();
+        xs.remove(0);
+        xs
+    }
+
+    /// Parses a snapshot by hand, checking its framing, that each tree is in
+    /// heap order and that the minimum root comes first. Returns the elements
+    /// in the order they were written.
+    fn parse<T: Element + Ord>(buf: &[u8]) -> Vec<T> {
+        let (mut r, crc) = buf.split_at(buf.len() - 4);
+        assert_eq!(crc, crc32_update(0, r).to_le_bytes(), "checksum");
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic).unwrap();
+        assert_eq!(magic, MAGIC);
+        assert_eq!(u16::decode(&mut r).unwrap(), VERSION);
+        let len = u64::decode(&mut r).unwrap();
+        let nroots = u64::decode(&mut r).unwrap();
+
+        // returns where the root of the tree is in `out`
+        fn tree<T: Element + Ord>(r: &mut &[u8], out: &mut Vec<T>) -> usize {
+            let degree = u32::decode(r).unwrap();
+            let i = out.len();
+            out.push(T::decode(r).unwrap());
+            for _ in 0..degree {
+                let c = tree(r, out);
+                assert!(out[i] <= out[c], "a child is less than its parent");
+            }
+            i
+        }
+
+        let mut out = Vec::new();
+        let roots = (0..nroots)
+            .map(|_| tree(&mut r, &mut out))
+            .collect::<Vec<_>>();
+        assert!(r.is_empty(), "trailing bytes");
+        assert_eq!(out.len() as u64, len);
+        if let Some(&min) = roots.first() {
+            assert!(roots.iter().all(|&j| out[min] <= out[j]), "min root first");
+        }
+        out
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32_update(0, b"123456789"), 0xcbf4_3926);
+        // incremental updates agree
+        assert_eq!(
+            crc32_update(crc32_update(0, b"1234"), b"56789"),
+            0xcbf4_3926
+        );
+    }
+
+    #[test]
+    fn golden_loads() {
+        let mut a = v1::FibonacciHeap::<u32>::read_snapshot(GOLDEN).unwrap();
+        let mut b = v2::FibonacciHeap::<u32>::read_snapshot(GOLDEN).unwrap();
+        assert_eq!(a.len(), 19);
+        assert_eq!(b.len(), 19);
+
+        let exp = golden_elements();
+        assert_eq!(std::iter::from_fn(|| a.pop()).collect::<Vec<_>>(), exp);
+        assert_eq!(std::iter::from_fn(|| b.pop()).collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    fn snapshots_are_well_formed() {
+        let mut a: v1::FibonacciHeap<u32> = golden_heap();
+        a.pop();
+        let mut b: v2::FibonacciHeap<u32> = golden_heap();
+        b.pop();
+
+        let mut bufs = [Vec::new(), Vec::new()];
+        a.write_snapshot(&mut bufs[0]).unwrap();
+        b.write_snapshot(&mut bufs[1]).unwrap();
+        for buf in bufs {
+            let mut xs = parse::<u32>(&buf);
+            xs.sort();
+            assert_eq!(xs, golden_elements());
+        }
+    }
+
+    #[test]
+    fn v1_and_v2_snapshots_are_interchangeable() {
+        let mut a: v1::FibonacciHeap<u32> = golden_heap();
+        a.pop();
+        let mut buf = Vec::new();
+        a.write_snapshot(&mut buf).unwrap();
+        let mut b = v2::FibonacciHeap::<u32>::read_snapshot(&buf[..]).unwrap();
+
+        buf.clear();
+        b.write_snapshot(&mut buf).unwrap();
+        let mut c = v1::FibonacciHeap::<u32>::read_snapshot(&buf[..]).unwrap();
+
+        while let Some(x) = a.pop() {
+            assert_eq!(b.pop(), Some(x));
+            assert_eq!(c.pop(), Some(x));
+        }
+        assert!(b.is_empty() && c.is_empty());
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let err = |buf: &[u8]| v2::FibonacciHeap::<u32>::read_snapshot(buf).err().unwrap();
+
+        // truncated
+        for n in [0, 4, 10, GOLDEN.len() - 1] {
+            err(&GOLDEN[..n]);
+        }
+
+        // bad magic
+        let mut buf = GOLDEN.to_vec();
+        buf[0] = b'X';
+        assert_eq!(err(&buf).kind(), io::ErrorKind::InvalidData);
+
+        // unknown version
+        let mut buf = GOLDEN.to_vec();
+        buf[8] = 2;
+        assert_eq!(err(&buf).kind(), io::ErrorKind::InvalidData);
+
+        // flipped payload bit
+        let mut buf = GOLDEN.to_vec();
+        let n = buf.len();
+        buf[n - 6] ^= 1;
+        assert_eq!(err(&buf).kind(), io::ErrorKind::InvalidData);
+
+        // flipped checksum bit
+        let mut buf = GOLDEN.to_vec();
+        let n = buf.len();
+        buf[n - 1] ^= 1;
+        assert_eq!(err(&buf).kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_broken_heap_order() {
+        // a valid frame, but a child is less than its parent
+        let mut buf = Vec::new();
+        buf.extend(MAGIC);
+        buf.extend(VERSION.to_le_bytes());
+        buf.extend(2u64.to_le_bytes());
+        buf.extend(1u64.to_le_bytes());
+        // root: degree 1, payload 2
+        buf.extend(1u32.to_le_bytes());
+        buf.extend(2u32.to_le_bytes());
+        // child: degree 0, payload 1
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(1u32.to_le_bytes());
+        let crc = crc32_update(0, &buf);
+        buf.extend(crc.to_le_bytes());
+
+        let e = v2::FibonacciHeap::<u32>::read_snapshot(&buf[..])
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = v1::FibonacciHeap::<u32>::read_snapshot(&buf[..])
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[quickcheck]
+    fn round_trip(xs: Vec<(u16, String)>) {
+        let mut heap = v2::FibonacciHeap::from_iter(xs);
+        heap.pop();
+
+        let mut buf = Vec::new();
+        heap.write_snapshot(&mut buf).unwrap();
+        assert_eq!(parse::<(u16, String)>(&buf).len(), heap.len());
+        let mut back = v2::FibonacciHeap::read_snapshot(&buf[..]).unwrap();
+
+        let mut buf2 = Vec::new();
+        back.write_snapshot(&mut buf2).unwrap();
+        assert_eq!(buf, buf2, "shape should be preserved");
+
+        assert_eq!(back.len(), heap.len());
+        while let Some(x) = heap.pop() {
+            assert_eq!(back.pop(), Some(x));
+        }
+    }
+}
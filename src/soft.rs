@@ -0,0 +1,408 @@
+//! A soft heap, after Chazelle, in the simplified form of Kaplan, Tarjan and
+//! Zwick.
+//!
+//! A soft heap trades exactness for speed: it may _corrupt_ elements, raising
+//! their keys to that of another element so that whole lists of them travel
+//! through the trees together. For an error rate `ε`, at most `εn` of the
+//! elements in the heap are corrupted at any time, `n` being the number of
+//! pushes so far. In return `push` is `O(1)` amortised and `pop` is
+//! `O(log 1/ε)` amortised, whatever the size of the heap.
+//!
+//! [`SoftHeap::pop`] removes an element with the least _current_ key, and
+//! says whether it was corrupted. [`select_kth`] uses that to find a good
+//! pivot, and selects in linear time.
+use crate::arena::Arena;
+use std::mem;
+
+pub struct SoftHeap<T> {
+    items: Arena<Item<T>>,
+    // the root of rank k, if any, at roots[k]
+    roots: Vec<Option<Box<Node<T>>>>,
+    // suffix_min[k] is the rank of the root with the least key of those of
+    // rank k or more
+    suffix_min: Vec<Option<usize>>,
+    // nodes of this rank or less hold a single element, so only their
+    // parents corrupt
+    r: usize,
+    len: usize,
+}
+
+struct Item<T> {
+    item: T,
+    next: Option<usize>,
+}
+
+/// A linked list of items, so that lists join in `O(1)` as they move up.
+#[derive(Clone, Copy, Default)]
+struct List {
+    ends: Option<(usize, usize)>,
+    len: usize,
+}
+
+struct Node<T> {
+    // the elements carpooling here, all with keys at most `key`
+    list: List,
+    key: T,
+    rank: usize,
+    // how many elements the node takes from its children when it runs out
+    size: usize,
+    left: Option<Box<Node<T>>>,
+    right: Option<Box<Node<T>>>,
+}
+
+impl<T: Ord + Clone> SoftHeap<T> {
+    /// A heap corrupting at most `epsilon` times as many elements as have
+    /// been pushed.
+    ///
+    /// # Panics
+    /// Panics if `epsilon` is not between 0 and 1.
+    pub fn new(epsilon: f64) -> Self {
+        assert!(epsilon > 0.0 && epsilon <= 1.0, "epsilon must be in (0, 1]");
+        Self {
+            items: Arena::new(),
+            roots: Vec::new(),
+            suffix_min: Vec::new(),
+            r: (1.0 / epsilon).log2().ceil() as usize + 5,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The key the next element will be popped at. It is at least that
+    /// element, more if the element is corrupted.
+    pub fn peek_key(&self) -> Option<&T> {
+        let k = self.suffix_min.first().copied().flatten()?;
+        self.roots[k].as_ref().map(|x| &x.key)
+    }
+
+    pub fn push(&mut self, item: T) {
+        let key = item.clone();
+        let i = self.items.insert(Item { item, next: None }).index;
+        let mut x = Box::new(Node::new(key, i));
+
+        // carry along the roots, as in a binary counter
+        let mut k = 0;
+        while let Some(y) = self.roots.get_mut(k).and_then(Option::take) {
+            x = self.link(x, y);
+            k += 1;
+        }
+
+        if self.roots.len() <= k {
+            self.roots.resize_with(k + 1, || None);
+            self.suffix_min.resize(k + 1, None);
+        }
+        self.roots[k] = Some(x);
+        self.len += 1;
+        self.update_suffix_min(k);
+    }
+
+    /// Removes an element with the least current key, and tells whether it
+    /// was corrupted, its key having been raised above its own.
+    pub fn pop(&mut self) -> Option<(T, bool)> {
+        let k = self.suffix_min.first().copied().flatten()?;
+        let root = self.roots[k].as_mut().expect("suffix_min is a root");
+        let (head, tail) = root.list.ends.expect("roots are not empty");
+        let Item { item, next } = self.items.remove(head);
+        root.list.ends = next.map(|next| (next, tail));
+        root.list.len -= 1;
+        let corrupted = item < root.key;
+        self.len -= 1;
+
+        // the root keeps its key until its list runs out
+        if root.list.len == 0 {
+            root.sift(&mut self.items);
+            if root.list.len == 0 {
+                self.roots[k] = None;
+            }
+            self.update_suffix_min(k);
+        }
+
+        Some((item, corrupted))
+    }
+
+    /// Makes a root of rank k + 1 from two of rank k.
+    fn link(&mut self, x: Box<Node<T>>, y: Box<Node<T>>) -> Box<Node<T>> {
+        let rank = x.rank + 1;
+        let mut z = Box::new(Node {
+            list: List::default(),
+            key: x.key.clone(),
+            rank,
+            size: if rank <= self.r {
+                1
+            } else {
+                (3 * x.size).div_ceil(2)
+            },
+            left: Some(x),
+            right: Some(y),
+        });
+
+        z.sift(&mut self.items);
+        z
+    }
+
+    fn update_suffix_min(&mut self, upto: usize) {
+        let mut min = self.suffix_min.get(upto + 1).copied().flatten();
+        for k in (0..=upto).rev() {
+            if let Some(x) = &self.roots[k] {
+                min = match min {
+                    Some(m) if self.roots[m].as_ref().unwrap().key <= x.key => Some(m),
+                    _ => Some(k),
+                };
+            }
+            self.suffix_min[k] = min;
+        }
+    }
+}
+
+impl<T: Ord + Clone> Node<T> {
+    fn new(key: T, item: usize) -> Self {
+        Self {
+            key,
+            list: List {
+                ends: Some((item, item)),
+                len: 1,
+            },
+            rank: 0,
+            size: 1,
+            left: None,
+            right: None,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.left.is_none() && self.right.is_none()
+    }
+
+    /// Refills the list from the children, corrupting the elements taken to
+    /// the key of the last list taken.
+    fn sift(&mut self, items: &mut Arena<Item<T>>) {
+        while self.list.len < self.size && !self.is_leaf() {
+            // take from the child with the lesser key
+            let swap = match (&self.left, &self.right) {
+                (None, _) => true,
+                (Some(l), Some(r)) => r.key < l.key,
+                (Some(_), None) => false,
+            };
+            if swap {
+                mem::swap(&mut self.left, &mut self.right);
+            }
+
+            let left = self.left.as_mut().expect("not a leaf");
+            self.list.append(mem::take(&mut left.list), items);
+            self.key = left.key.clone();
+            if left.is_leaf() {
+                self.left = None;
+            } else {
+                left.sift(items);
+            }
+        }
+    }
+}
+
+impl List {
+    fn append<T>(&mut self, other: List, items: &mut Arena<Item<T>>) {
+        self.ends = match (self.ends, other.ends) {
+            (Some((head, tail)), Some((first, last))) => {
+                items[tail].next = Some(first);
+                Some((head, last))
+            }
+            (ends, other) => ends.or(other),
+        };
+        self.len += other.len;
+    }
+}
+
+impl<T: Ord + Clone> Extend<T> for SoftHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+/// The `k`th least of `items`, counting from 0, in `O(n)`.
+///
+/// Each round pops a third of the items from a [`SoftHeap`] with `ε = 1/3`.
+/// The greatest of those has at least a third of the items on either side,
+/// so partitioning around it leaves at most two thirds for the next round.
+///
+/// # Panics
+/// Panics if `k` is not less than the number of items.
+pub fn select_kth<T: Ord>(mut items: Vec<T>, mut k: usize) -> T {
+    assert!(k < items.len(), "k out of range");
+
+    loop {
+        if items.len() <= 32 {
+            items.sort_unstable();
+            return items.swap_remove(k);
+        }
+
+        // tie-break by index, so the pivot is a single position
+        let mut heap = SoftHeap::new(1.0 / 3.0);
+        heap.extend(items.iter().zip(0..));
+        let pivot = (0..items.len().div_ceil(3))
+            .filter_map(|_| heap.pop())
+            .map(|((_, i), _)| i)
+            .max_by_key(|&i| (&items[i], i))
+            .expect("items are not empty");
+        drop(heap);
+
+        let pivot = items.swap_remove(pivot);
+        let (mut less, mut greater) = (Vec::new(), Vec::new());
+        let mut equal = 1;
+        for x in items {
+            match x.cmp(&pivot) {
+                std::cmp::Ordering::Less => less.push(x),
+                std::cmp::Ordering::Equal => equal += 1,
+                std::cmp::Ordering::Greater => greater.push(x),
+            }
+        }
+
+        if k < less.len() {
+            items = less;
+        } else if k < less.len() + equal {
+            return pivot;
+        } else {
+            k -= less.len() + equal;
+            items = greater;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+
+    // checks the heap order and the suffix minima, returning the number of
+    // elements and how many of them are corrupted
+    fn verify(heap: &SoftHeap<u32>) -> (usize, usize) {
+        fn walk(heap: &SoftHeap<u32>, x: &Node<u32>, counts: &mut (usize, usize)) {
+            let mut len = 0;
+            let mut next = x.list.ends.map(|(head, _)| head);
+            while let Some(i) = next {
+                let item = heap.items[i].item;
+                assert!(item <= x.key);
+                counts.0 += 1;
+                counts.1 += (item < x.key) as usize;
+                len += 1;
+                next = heap.items[i].next;
+            }
+            assert_eq!(len, x.list.len);
+
+            for child in [&x.left, &x.right].into_iter().flatten() {
+                assert!(child.list.len > 0);
+                assert!(x.key <= child.key);
+                assert_eq!(child.rank + 1, x.rank);
+                walk(heap, child, counts);
+            }
+        }
+
+        let mut counts = (0, 0);
+        for (k, root) in heap.roots.iter().enumerate() {
+            if let Some(x) = root {
+                assert!(x.list.len > 0);
+                assert_eq!(x.rank, k);
+                walk(heap, x, &mut counts);
+            }
+        }
+
+        let min = heap.roots.iter().flatten().map(|x| &x.key).min();
+        assert_eq!(heap.peek_key(), min);
+        counts
+    }
+
+    #[quickcheck]
+    fn small_epsilon_is_exact(xs: Vec<u32>) {
+        let mut heap = SoftHeap::new(1e-9);
+        heap.extend(xs.iter().copied());
+        assert_eq!(heap.len(), xs.len());
+
+        let mut xs = xs;
+        xs.sort();
+        let popped = std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>();
+        assert!(popped.iter().all(|&(_, corrupted)| !corrupted));
+        assert_eq!(popped.into_iter().map(|(x, _)| x).collect::<Vec<_>>(), xs);
+    }
+
+    #[quickcheck]
+    fn corruption_is_bounded(ops: Vec<(bool, u32)>, epsilon: u8) {
+        let epsilon = 1.0 / (epsilon % 8 + 1) as f64;
+        let mut heap = SoftHeap::new(epsilon);
+        let mut model = Vec::new();
+        let mut pushes = 0;
+
+        for &(pop, x) in &ops {
+            if pop {
+                let key = heap.peek_key().copied();
+                match heap.pop() {
+                    Some((x, corrupted)) => {
+                        let key = key.unwrap();
+                        assert_eq!(corrupted, x < key);
+                        let j = model.iter().position(|&y| y == x).unwrap();
+                        model.swap_remove(j);
+                    }
+                    None => assert!(model.is_empty()),
+                }
+            } else {
+                heap.push(x);
+                model.push(x);
+                pushes += 1;
+            }
+
+            let (len, corrupted) = verify(&heap);
+            assert_eq!(len, model.len());
+            assert_eq!(heap.len(), model.len());
+            assert!(corrupted as f64 <= epsilon * pushes as f64);
+        }
+    }
+
+    #[test]
+    fn corrupts_at_scale() {
+        let mut heap = SoftHeap::new(0.125);
+        heap.extend((0..100_000u32).map(|x| x.wrapping_mul(2_654_435_761)));
+        let (len, corrupted) = verify(&heap);
+        assert_eq!(len, 100_000);
+        assert!(0 < corrupted && corrupted <= 12_500);
+
+        // popped keys never go down
+        let mut last = 0;
+        while let Some(&key) = heap.peek_key() {
+            assert!(last <= key);
+            last = key;
+            heap.pop();
+        }
+    }
+
+    #[quickcheck]
+    fn selects_like_sorting(xs: Vec<u16>, k: usize) {
+        if xs.is_empty() {
+            return;
+        }
+        let k = k % xs.len();
+
+        let mut sorted = xs.clone();
+        sorted.sort();
+        assert_eq!(select_kth(xs, k), sorted[k]);
+    }
+
+    #[test]
+    fn selects_from_many() {
+        let xs = (0..100_000u32)
+            .map(|x| x.wrapping_mul(2_654_435_761) % 1000)
+            .collect::<Vec<_>>();
+        let mut sorted = xs.clone();
+        sorted.sort();
+
+        for k in [0, 1, 33_333, 50_000, 99_999] {
+            assert_eq!(select_kth(xs.clone(), k), sorted[k]);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Structural statistics and operation counters of the heaps.
+//!
+//! The structure is measured on demand by `stats`, walking the whole forest.
+//! The counters are cumulative over the life of a heap and are only kept with
+//! the `stats` feature, without which they take no space and cost nothing.
+use std::ops::AddAssign;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// The total number of nodes.
+    pub nodes: usize,
+    /// The length of the root list.
+    pub roots: usize,
+    pub max_degree: usize,
+    /// `degrees[d]` is the number of nodes with `d` children.
+    pub degrees: Vec<usize>,
+    /// The height of each tree in root order, where a lone root has height 1.
+    pub heights: Vec<usize>,
+    /// `None` unless the `stats` feature is enabled.
+    pub counters: Option<OpCounters>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCounters {
+    /// Trees linked under another while rebalancing.
+    pub links: u64,
+    /// Comparisons between elements.
+    pub comparisons: u64,
+    /// Scratch buffers allocated while rebalancing.
+    pub allocations: u64,
+}
+
+impl AddAssign for OpCounters {
+    fn add_assign(&mut self, other: Self) {
+        self.links += other.links;
+        self.comparisons += other.comparisons;
+        self.allocations += other.allocations;
+    }
+}
+
+impl HeapStats {
+    pub(crate) fn from_forest<'a, N: 'a>(
+        roots: impl IntoIterator<Item = &'a N>,
+        children: fn(&N) -> &[N],
+        counters: Option<OpCounters>,
+    ) -> Self {
+        let mut stats = HeapStats {
+            counters,
+            ..Default::default()
+        };
+
+        for root in roots {
+            let mut height = 0;
+            let mut stack = vec![(root, 1)];
+            while let Some((node, depth)) = stack.pop() {
+                let children = children(node);
+                let degree = children.len();
+
+                if stats.degrees.len() <= degree {
+                    stats.degrees.resize(degree + 1, 0);
+                }
+                stats.degrees[degree] += 1;
+                stats.max_degree = stats.max_degree.max(degree);
+                stats.nodes += 1;
+                height = height.max(depth);
+
+                stack.extend(children.iter().map(|c| (c, depth + 1)));
+            }
+
+            stats.roots += 1;
+            stats.heights.push(height);
+        }
+
+        stats
+    }
+}
+
+/// The counters kept by a heap, zero-sized without the `stats` feature.
+#[derive(Clone, Default)]
+pub(crate) struct Counters {
+    #[cfg(feature = "stats")]
+    ops: OpCounters,
+}
+
+#[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+impl Counters {
+    #[inline]
+    pub(crate) fn link(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.ops.links += 1;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn compare(&mut self, n: usize) {
+        #[cfg(feature = "stats")]
+        {
+            self.ops.comparisons += n as u64;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn alloc(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.ops.allocations += 1;
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<OpCounters> {
+        #[cfg(feature = "stats")]
+        return Some(self.ops);
+
+        #[cfg(not(feature = "stats"))]
+        None
+    }
+}
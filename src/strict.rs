@@ -0,0 +1,1095 @@
+//! A strict Fibonacci heap, after Brodal, Lagogiannis and Tarjan.
+//!
+//! The amortised heaps can leave a long root list for a single
+//! [`pop`](StrictFibonacciHeap::pop) to consolidate. This heap does a little
+//! of that work in every operation instead, so that every bound is
+//! worst-case: `push`, `decrease_key` and
+//! [`meld`](StrictFibonacciHeap::meld) are `O(1)`, and `pop` is `O(log n)`.
+//!
+//! The heap is a single tree. Each node is _active_ or _passive_, and an
+//! active node with a passive parent is an _active root_. Active nodes have a
+//! rank, their number of active children, and active non-roots have a loss,
+//! the number of active children they have lost. Each operation ends with a
+//! bounded number of reductions, which keep the number of active roots, the
+//! total loss and the degree of the root logarithmic:
+//!
+//! - linking two active roots of equal rank,
+//! - linking three passive children of the root into an active chain,
+//! - cutting a node with a loss of two, or linking two nodes with a loss of
+//!   one and equal rank.
+//!
+//! A node is active when it carries the record of its heap, so a meld makes
+//! every node of the smaller heap passive just by dropping that heap's
+//! record. Nodes live in an arena where they never move, so a meld takes in
+//! the other heap's nodes where they are, and a push never has to copy the
+//! nodes already there.
+use crate::arena::StableArena;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub use crate::arena::Handle;
+
+pub struct StrictFibonacciHeap<T> {
+    nodes: StableArena<Node>,
+    // elements live apart from the nodes, so the root can trade its element
+    // with a node which drops below it
+    entries: StableArena<Entry<T>>,
+    // the nodes with this record are active, so a meld can make all of a
+    // heap's nodes passive at once by retiring its record
+    record: u64,
+    root: Option<usize>,
+    len: usize,
+    // the leftmost linkable child of the root, see `attach`
+    linkable: Option<usize>,
+    // the front of a circular queue of all the non-root nodes
+    queue: Option<usize>,
+    // active roots and nodes of loss one, by rank
+    ranks: Vec<[Bucket; 2]>,
+    // for each kind, the ranks with two or more nodes
+    pairs: [Vec<usize>; 2],
+    // nodes with a loss of two or more
+    lossy: Option<usize>,
+    // children of the popped root, kept to avoid allocating on every pop
+    scratch: Vec<usize>,
+}
+
+const ROOTS: usize = 0;
+const LOSS: usize = 1;
+
+// the record of passive nodes, which no heap has
+const PASSIVE: u64 = 0;
+
+// ranks stay below 2 log n + 6, so the rank buckets never have to move
+const MAX_RANKS: usize = 2 * usize::BITS as usize + 8;
+
+struct Entry<T> {
+    item: T,
+    node: usize,
+}
+
+struct Node {
+    entry: usize,
+    record: u64,
+    parent: Option<usize>,
+    // children, active ones to the left of passive ones
+    first: Option<usize>,
+    last: Option<usize>,
+    prev: Option<usize>,
+    next: Option<usize>,
+    rank: usize,
+    loss: usize,
+    // where the node is listed, and its neighbours there
+    fix: Fix,
+    fix_prev: Option<usize>,
+    fix_next: Option<usize>,
+    queue_prev: usize,
+    queue_next: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fix {
+    None,
+    Root(usize),
+    Loss(usize),
+    Lossy,
+}
+
+#[derive(Default, Clone)]
+struct Bucket {
+    head: Option<usize>,
+    len: usize,
+    // the position of the rank in `pairs`, while len >= 2
+    pair: Option<usize>,
+}
+
+impl<T: Ord> StrictFibonacciHeap<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// A heap which can hold `capacity` elements before the arena grows.
+    pub fn with_capacity(capacity: usize) -> Self {
+        static RECORDS: AtomicU64 = AtomicU64::new(PASSIVE + 1);
+        let mut heap = Self {
+            nodes: StableArena::new(),
+            entries: StableArena::new(),
+            record: RECORDS.fetch_add(1, Ordering::Relaxed),
+            root: None,
+            len: 0,
+            linkable: None,
+            queue: None,
+            ranks: Vec::new(),
+            pairs: [Vec::new(), Vec::new()],
+            lossy: None,
+            scratch: Vec::new(),
+        };
+        heap.reserve(capacity);
+        heap
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+        self.entries.reserve(additional);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.map(|r| &self.entries[self.nodes[r].entry].item)
+    }
+
+    /// The element `handle` refers to, or `None` if the handle is stale.
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.entries.get(handle).map(|e| &e.item)
+    }
+
+    pub fn push(&mut self, item: T) -> Handle {
+        let node = self.nodes.insert(Node::new()).index;
+        let handle = self.entries.insert(Entry { item, node });
+        self.nodes[node].entry = handle.index;
+        self.len += 1;
+
+        // a meld with a heap of one node
+        let Some(root) = self.root else {
+            self.root = Some(node);
+            return handle;
+        };
+
+        let loser = if self.less(node, root) {
+            self.root = Some(node);
+            self.linkable = None;
+            root
+        } else {
+            node
+        };
+        self.attach(self.root.unwrap(), loser);
+
+        let node = &mut self.nodes[loser];
+        node.queue_prev = loser;
+        node.queue_next = loser;
+        self.queue = self.concat(Some(loser), self.queue);
+
+        self.reduce_active_roots();
+        self.reduce_root_degree();
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let root = self.root?;
+        self.len -= 1;
+
+        // the least child becomes the root
+        let mut min = None;
+        let mut next = self.nodes[root].first;
+        while let Some(c) = next {
+            if min.is_none_or(|m| self.less(c, m)) {
+                min = Some(c);
+            }
+            next = self.nodes[c].next;
+        }
+
+        let entry = self.nodes[root].entry;
+        let Some(x) = min else {
+            self.nodes.remove(root);
+            self.clear_structure();
+            return Some(self.entries.remove(entry).item);
+        };
+
+        self.dequeue(x);
+        if self.is_active(x) {
+            // the root is passive, so its active children become active roots
+            self.unfix(x);
+            self.nodes[x].record = PASSIVE;
+
+            let mut next = self.nodes[x].first;
+            while let Some(c) = next.filter(|&c| self.is_active(c)) {
+                self.nodes[c].loss = 0;
+                self.refix(c);
+                next = self.nodes[c].next;
+            }
+        }
+
+        // the new root takes on the other children, which `attach` puts in
+        // the order kept for the root
+        let mut kids = std::mem::take(&mut self.scratch);
+        for parent in [x, root] {
+            let mut next = self.nodes[parent].first;
+            while let Some(c) = next {
+                if c != x {
+                    kids.push(c);
+                }
+                next = self.nodes[c].next;
+            }
+        }
+        self.nodes.remove(root);
+
+        let node = &mut self.nodes[x];
+        node.parent = None;
+        node.prev = None;
+        node.next = None;
+        node.first = None;
+        node.last = None;
+        self.root = Some(x);
+        self.linkable = None;
+        for c in kids.drain(..) {
+            self.attach(x, c);
+        }
+        self.scratch = kids;
+
+        // bound the degrees of non-roots, by moving passive children of the
+        // front of the queue up to the root
+        for _ in 0..2 {
+            let Some(y) = self.queue else {
+                break;
+            };
+            self.queue = Some(self.nodes[y].queue_next);
+
+            for _ in 0..2 {
+                match self.nodes[y].last {
+                    Some(c) if !self.is_active(c) => {
+                        self.detach(c);
+                        self.attach(x, c);
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        // every operation leaves the total loss, the active roots and the
+        // root degree within R = 2 log n + 6, and x has at most R children,
+        // so a bounded number of reductions is always enough. each loss
+        // reduction takes away loss, each active root reduction lowers
+        // 2 * active roots + root degree, and each root degree reduction
+        // trades two children of the root for one active root
+        let bound = 2 * (self.len + 1).ilog2() as usize + 6;
+        for _ in 0..bound + 1 {
+            if !self.reduce_loss() {
+                break;
+            }
+        }
+        for _ in 0..15 * bound + 20 {
+            if !self.reduce_active_roots() && !self.reduce_root_degree() {
+                break;
+            }
+        }
+        debug_assert!(self.lossy.is_none() && self.pairs.iter().all(Vec::is_empty));
+
+        Some(self.entries.remove(entry).item)
+    }
+
+    /// Lowers the element `handle` refers to down to `item`.
+    ///
+    /// # Panics
+    /// Panics if the handle is stale, or `item` is greater than the element.
+    pub fn decrease_key(&mut self, handle: Handle, item: T) {
+        let entry = self.entries.get(handle).expect("handle is stale");
+        assert!(item <= entry.item, "decrease_key would increase the key");
+        let x = entry.node;
+        self.entries[handle.index].item = item;
+
+        let root = self.root.expect("not empty");
+        if x == root {
+            return;
+        }
+
+        // cut x up to the root
+        let y = self.nodes[x].parent.expect("non-root has a parent");
+        if y != root {
+            let active = self.is_active(x);
+            self.detach(x);
+            if active {
+                self.nodes[x].loss = 0;
+                if self.is_active(y) {
+                    self.lose_child(y);
+                    self.refix(y);
+                }
+            }
+            self.attach(root, x);
+            self.refix(x);
+        }
+
+        if self.less(x, root) {
+            // the root keeps its place in the tree, and takes the element
+            let (ex, er) = (self.nodes[x].entry, self.nodes[root].entry);
+            self.nodes[x].entry = er;
+            self.nodes[root].entry = ex;
+            self.entries[er].node = x;
+            self.entries[ex].node = root;
+        }
+
+        self.reduce_loss();
+        for _ in 0..6 {
+            if !self.reduce_active_roots() {
+                break;
+            }
+        }
+        for _ in 0..4 {
+            if !self.reduce_root_degree() {
+                break;
+            }
+        }
+    }
+
+    /// Moves all the elements of `other` into `self`, in `O(1)`.
+    ///
+    /// The nodes of `other` join those of `self` where they are, and the
+    /// smaller heap's nodes all become passive at once, by retiring its
+    /// record. Handles into `self` stay valid, and handles into `other` go
+    /// stale.
+    pub fn meld(&mut self, other: Self) {
+        self.nodes.append(other.nodes);
+        self.entries.append(other.entries);
+        let Some(b) = other.root else {
+            return;
+        };
+
+        // the larger heap keeps its active nodes, the smaller loses them
+        let keep_other = other.len > self.len;
+        self.len += other.len;
+        if keep_other {
+            self.record = other.record;
+            self.ranks = other.ranks;
+            self.pairs = other.pairs;
+            self.lossy = other.lossy;
+        }
+        let Some(a) = self.root else {
+            self.root = Some(b);
+            self.linkable = other.linkable;
+            self.queue = other.queue;
+            return;
+        };
+
+        // the root of the smaller heap has only passive, so linkable, children
+        let loser = if self.less(a, b) {
+            if keep_other {
+                self.linkable = self.nodes[a].first;
+            }
+            b
+        } else {
+            self.root = Some(b);
+            self.linkable = match keep_other {
+                true => other.linkable,
+                false => self.nodes[b].first,
+            };
+            a
+        };
+        self.attach(self.root.unwrap(), loser);
+
+        let node = &mut self.nodes[loser];
+        node.queue_prev = loser;
+        node.queue_next = loser;
+        let queue = self.concat(other.queue, Some(loser));
+        self.queue = self.concat(queue, self.queue);
+
+        self.reduce_active_roots();
+        self.reduce_root_degree();
+    }
+
+    /// Links two active roots of equal rank, if there are any.
+    fn reduce_active_roots(&mut self) -> bool {
+        let Some((a, b)) = self.pair(ROOTS) else {
+            return false;
+        };
+        let (x, y) = self.order(a, b);
+
+        self.detach(y);
+        self.attach(x, y);
+        self.nodes[x].rank += 1;
+        self.refix(x);
+        self.refix(y);
+
+        // keep the degree of x down
+        if let Some(z) = self.nodes[x].last.filter(|&z| !self.is_active(z)) {
+            self.detach(z);
+            self.attach(self.root.expect("not empty"), z);
+        }
+        true
+    }
+
+    /// Links the three rightmost children of the root into an active chain,
+    /// if they are all linkable.
+    fn reduce_root_degree(&mut self) -> bool {
+        let Some(root) = self.root else {
+            return false;
+        };
+        let c = self.nodes[root].last.filter(|&c| self.is_linkable(c));
+        let b = c.and_then(|c| self.nodes[c].prev);
+        let a = b.and_then(|b| self.nodes[b].prev);
+        let (Some(a), Some(b), Some(c)) = (a, b, c) else {
+            return false;
+        };
+        if !self.is_linkable(a) || !self.is_linkable(b) {
+            return false;
+        }
+
+        let mut chain = [a, b, c];
+        chain.sort_unstable_by(|&a, &b| self.cmp(a, b));
+        for i in chain {
+            self.detach(i);
+            let node = &mut self.nodes[i];
+            node.record = self.record;
+            node.loss = 0;
+            // any listing is left over from a retired record
+            node.fix = Fix::None;
+        }
+
+        let [x, y, z] = chain;
+        self.nodes[x].rank = 1;
+        self.nodes[y].rank = 1;
+        self.nodes[z].rank = 0;
+        self.attach(y, z);
+        self.attach(x, y);
+        self.attach(root, x);
+        self.refix(x);
+        true
+    }
+
+    /// Cuts a node with a loss of two or more up to the root, or links two
+    /// nodes of loss one and equal rank, if there are any.
+    fn reduce_loss(&mut self) -> bool {
+        if let Some(x) = self.lossy {
+            let y = self.nodes[x].parent.expect("lossy nodes are not roots");
+            self.detach(x);
+            self.nodes[x].loss = 0;
+            self.attach(self.root.expect("not empty"), x);
+            self.lose_child(y);
+            self.refix(x);
+            self.refix(y);
+            return true;
+        }
+
+        let Some((a, b)) = self.pair(LOSS) else {
+            return false;
+        };
+        let (x, y) = self.order(a, b);
+        let z = self.nodes[y].parent.expect("lossy nodes are not roots");
+
+        self.detach(y);
+        if z != x {
+            self.lose_child(z);
+            self.nodes[x].rank += 1;
+            self.nodes[x].loss = 0;
+        }
+        // else y is already a child of x, and only moves to its left, so x
+        // keeps its rank and loss
+        self.attach(x, y);
+        self.nodes[y].loss = 0;
+        for i in [x, y, z] {
+            self.refix(i);
+        }
+        true
+    }
+
+    /// Accounts for an active node losing an active child.
+    fn lose_child(&mut self, y: usize) {
+        self.nodes[y].rank -= 1;
+        if self.nodes[y].parent.is_some_and(|p| self.is_active(p)) {
+            self.nodes[y].loss += 1;
+        }
+    }
+
+    fn is_active(&self, i: usize) -> bool {
+        self.nodes[i].record == self.record
+    }
+
+    /// Whether a node is passive with only passive children, so it can be
+    /// made active by [`Self::reduce_root_degree`].
+    fn is_linkable(&self, i: usize) -> bool {
+        !self.is_active(i) && self.nodes[i].first.is_none_or(|c| !self.is_active(c))
+    }
+
+    fn cmp(&self, a: usize, b: usize) -> std::cmp::Ordering {
+        // ties are broken by entry, so that no node is linked below its own
+        // descendant
+        let (ea, eb) = (self.nodes[a].entry, self.nodes[b].entry);
+        (&self.entries[ea].item, ea).cmp(&(&self.entries[eb].item, eb))
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        self.cmp(a, b).is_lt()
+    }
+
+    fn order(&self, a: usize, b: usize) -> (usize, usize) {
+        match self.less(a, b) {
+            true => (a, b),
+            false => (b, a),
+        }
+    }
+
+    /// Makes `c` a child of `p`. Active children go to the left, passive
+    /// ones to the right, and for the root, linkable children rightmost.
+    fn attach(&mut self, p: usize, c: usize) {
+        let before = if self.is_active(c) {
+            debug_assert!(self.root == Some(p) || self.is_active(p));
+            self.nodes[p].first
+        } else if self.root == Some(p) && !self.is_linkable(c) {
+            self.linkable
+        } else {
+            None
+        };
+
+        let after = match before {
+            Some(b) => self.nodes[b].prev,
+            None => self.nodes[p].last,
+        };
+
+        let node = &mut self.nodes[c];
+        node.parent = Some(p);
+        node.prev = after;
+        node.next = before;
+        match after {
+            Some(a) => self.nodes[a].next = Some(c),
+            None => self.nodes[p].first = Some(c),
+        }
+        match before {
+            Some(b) => self.nodes[b].prev = Some(c),
+            None => self.nodes[p].last = Some(c),
+        }
+
+        if self.root == Some(p) && self.linkable.is_none() && self.is_linkable(c) {
+            self.linkable = Some(c);
+        }
+    }
+
+    /// Cuts `c` away from its parent.
+    fn detach(&mut self, c: usize) {
+        let node = &mut self.nodes[c];
+        let p = node.parent.take().expect("non-root has a parent");
+        let (prev, next) = (node.prev.take(), node.next.take());
+        match prev {
+            Some(s) => self.nodes[s].next = next,
+            None => self.nodes[p].first = next,
+        }
+        match next {
+            Some(s) => self.nodes[s].prev = prev,
+            None => self.nodes[p].last = prev,
+        }
+
+        if self.linkable == Some(c) {
+            self.linkable = next;
+        }
+
+        // a passive child of the root which loses its last active child
+        // becomes linkable, and belongs on the right
+        let root = self.root.expect("not empty");
+        if self.is_active(c) && self.nodes[p].parent == Some(root) && self.is_linkable(p) {
+            self.detach(p);
+            self.attach(root, p);
+        }
+    }
+
+    fn pair(&self, kind: usize) -> Option<(usize, usize)> {
+        let &r = self.pairs[kind].last()?;
+        let a = self.ranks[r][kind].head?;
+        let b = self.nodes[a].fix_next?;
+        Some((a, b))
+    }
+
+    /// Lists `i` where it belongs now, after a change to its rank, loss or
+    /// parent.
+    fn refix(&mut self, i: usize) {
+        self.unfix(i);
+        if !self.is_active(i) {
+            return;
+        }
+
+        let node = &self.nodes[i];
+        let fix = if !node.parent.is_some_and(|p| self.is_active(p)) {
+            Fix::Root(node.rank)
+        } else {
+            match node.loss {
+                0 => return,
+                1 => Fix::Loss(node.rank),
+                _ => Fix::Lossy,
+            }
+        };
+
+        match fix {
+            Fix::Root(r) => self.enlist(ROOTS, r, i),
+            Fix::Loss(r) => self.enlist(LOSS, r, i),
+            _ => {
+                let head = self.lossy.replace(i);
+                self.link_fix(i, head);
+            }
+        }
+        self.nodes[i].fix = fix;
+    }
+
+    fn unfix(&mut self, i: usize) {
+        let fix = std::mem::replace(&mut self.nodes[i].fix, Fix::None);
+        if fix == Fix::None || !self.is_active(i) {
+            // a node made passive by a meld may still name the lists of its
+            // retired record
+            return;
+        }
+
+        let node = &mut self.nodes[i];
+        let (prev, next) = (node.fix_prev.take(), node.fix_next.take());
+        if let Some(n) = next {
+            self.nodes[n].fix_prev = prev;
+        }
+
+        let head = match fix {
+            Fix::Root(r) => {
+                self.delist(ROOTS, r);
+                &mut self.ranks[r][ROOTS].head
+            }
+            Fix::Loss(r) => {
+                self.delist(LOSS, r);
+                &mut self.ranks[r][LOSS].head
+            }
+            _ => &mut self.lossy,
+        };
+        match prev {
+            Some(p) => self.nodes[p].fix_next = next,
+            None => *head = next,
+        }
+    }
+
+    fn enlist(&mut self, kind: usize, r: usize, i: usize) {
+        if self.ranks.len() <= r {
+            if self.ranks.capacity() == 0 {
+                // once, so that no later push or decrease_key reallocates
+                self.ranks.reserve_exact(MAX_RANKS);
+                self.pairs
+                    .iter_mut()
+                    .for_each(|p| p.reserve_exact(MAX_RANKS));
+            }
+            self.ranks.resize(r + 1, Default::default());
+        }
+
+        let bucket = &mut self.ranks[r][kind];
+        let head = bucket.head.replace(i);
+        bucket.len += 1;
+        if bucket.len == 2 {
+            bucket.pair = Some(self.pairs[kind].len());
+            self.pairs[kind].push(r);
+        }
+        self.link_fix(i, head);
+    }
+
+    /// Accounts for a node leaving the bucket of rank `r`.
+    fn delist(&mut self, kind: usize, r: usize) {
+        let bucket = &mut self.ranks[r][kind];
+        bucket.len -= 1;
+        if bucket.len == 1 {
+            let pos = bucket.pair.take().expect("paired");
+            self.pairs[kind].swap_remove(pos);
+            if let Some(&moved) = self.pairs[kind].get(pos) {
+                self.ranks[moved][kind].pair = Some(pos);
+            }
+        }
+    }
+
+    fn link_fix(&mut self, i: usize, head: Option<usize>) {
+        self.nodes[i].fix_prev = None;
+        self.nodes[i].fix_next = head;
+        if let Some(h) = head {
+            self.nodes[h].fix_prev = Some(i);
+        }
+    }
+
+    /// Joins two circular queues, returning the front.
+    fn concat(&mut self, a: Option<usize>, b: Option<usize>) -> Option<usize> {
+        let (Some(a), Some(b)) = (a, b) else {
+            return a.or(b);
+        };
+
+        let a_back = self.nodes[a].queue_prev;
+        let b_back = self.nodes[b].queue_prev;
+        self.nodes[a_back].queue_next = b;
+        self.nodes[b].queue_prev = a_back;
+        self.nodes[b_back].queue_next = a;
+        self.nodes[a].queue_prev = b_back;
+        Some(a)
+    }
+
+    fn dequeue(&mut self, i: usize) {
+        let Node {
+            queue_prev: prev,
+            queue_next: next,
+            ..
+        } = self.nodes[i];
+
+        if next == i {
+            self.queue = None;
+            return;
+        }
+        self.nodes[prev].queue_next = next;
+        self.nodes[next].queue_prev = prev;
+        if self.queue == Some(i) {
+            self.queue = Some(next);
+        }
+    }
+
+    /// Forgets the structure around the last node, once it is popped.
+    fn clear_structure(&mut self) {
+        self.root = None;
+        self.linkable = None;
+        self.queue = None;
+        self.ranks.clear();
+        self.pairs.iter_mut().for_each(Vec::clear);
+        self.lossy = None;
+    }
+}
+
+impl Node {
+    fn new() -> Self {
+        Self {
+            entry: 0,
+            record: PASSIVE,
+            parent: None,
+            first: None,
+            last: None,
+            prev: None,
+            next: None,
+            rank: 0,
+            loss: 0,
+            fix: Fix::None,
+            fix_prev: None,
+            fix_next: None,
+            queue_prev: 0,
+            queue_next: 0,
+        }
+    }
+}
+
+impl<T: Ord> Default for StrictFibonacciHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for StrictFibonacciHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = Self::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Ord> Extend<T> for StrictFibonacciHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        if let (_, Some(upr)) = iter.size_hint() {
+            self.reserve(upr);
+        }
+
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2;
+    use quickcheck_macros::*;
+    use rand::prelude::*;
+
+    // an element and the id it is known by in the reference heap, which
+    // makes every element distinct
+    type Item = (u32, usize);
+
+    /// Checks the tree, the lists kept alongside it, and the logarithmic
+    /// bounds the reductions are there to keep.
+    fn verify<T: Ord>(heap: &StrictFibonacciHeap<T>) {
+        let Some(root) = heap.root else {
+            assert_eq!(heap.len, 0);
+            assert_eq!(heap.queue, None);
+            assert!(heap.pairs.iter().all(Vec::is_empty));
+            assert_eq!(heap.lossy, None);
+            return;
+        };
+        assert!(!heap.is_active(root), "the root is passive");
+        assert_eq!(heap.nodes[root].parent, None);
+
+        let mut roots = vec![0; heap.ranks.len()];
+        let mut loss = vec![0; heap.ranks.len()];
+        let (mut lossy, mut total_loss, mut active_roots) = (0, 0, 0);
+        let mut max_degree = 0;
+
+        let mut count = 0;
+        let mut stack = vec![root];
+        while let Some(i) = stack.pop() {
+            count += 1;
+            let node = &heap.nodes[i];
+            assert_eq!(heap.entries[node.entry].node, i);
+
+            let mut kids = Vec::new();
+            let mut next = node.first;
+            while let Some(c) = next {
+                let child = &heap.nodes[c];
+                assert_eq!(child.parent, Some(i));
+                assert_eq!(child.prev, kids.last().copied());
+                assert!(heap.less(i, c), "heap ordered");
+                kids.push(c);
+                next = child.next;
+            }
+            assert_eq!(node.last, kids.last().copied());
+            if i != root {
+                max_degree = max_degree.max(kids.len());
+            }
+
+            // active children, then passive, then (for the root) linkable
+            let zone = |c| match (heap.is_active(c), i == root && heap.is_linkable(c)) {
+                (true, _) => 0,
+                (false, false) => 1,
+                (false, true) => 2,
+            };
+            assert!(kids.windows(2).all(|w| zone(w[0]) <= zone(w[1])));
+            if i == root {
+                assert_eq!(heap.linkable, kids.iter().copied().find(|&c| zone(c) == 2));
+            }
+
+            if heap.is_active(i) {
+                let active = kids.iter().filter(|&&c| heap.is_active(c));
+                assert_eq!(node.rank, active.clone().count());
+
+                // the i-th rightmost active child has rank + loss >= i - 1
+                for (k, &c) in active.rev().enumerate() {
+                    assert!(heap.nodes[c].rank + heap.nodes[c].loss >= k);
+                }
+
+                let parent = node.parent.expect("the root is passive");
+                let expect = if !heap.is_active(parent) {
+                    assert_eq!(node.loss, 0, "active roots have no loss");
+                    active_roots += 1;
+                    roots[node.rank] += 1;
+                    Fix::Root(node.rank)
+                } else {
+                    total_loss += node.loss;
+                    match node.loss {
+                        0 => Fix::None,
+                        1 => {
+                            loss[node.rank] += 1;
+                            Fix::Loss(node.rank)
+                        }
+                        _ => {
+                            lossy += 1;
+                            Fix::Lossy
+                        }
+                    }
+                };
+                assert_eq!(node.fix, expect);
+            }
+
+            stack.extend(kids);
+        }
+        assert_eq!(count, heap.len);
+
+        // the lists match the tree
+        for (r, [a, b]) in heap.ranks.iter().enumerate() {
+            assert_eq!((a.len, b.len), (roots[r], loss[r]));
+            for (kind, bucket) in [(ROOTS, a), (LOSS, b)] {
+                let mut n = 0;
+                let mut next = bucket.head;
+                while let Some(i) = next {
+                    n += 1;
+                    next = heap.nodes[i].fix_next;
+                }
+                assert_eq!(n, bucket.len);
+                assert_eq!(heap.pairs[kind].contains(&r), bucket.len >= 2);
+                if let Some(pos) = bucket.pair {
+                    assert_eq!(heap.pairs[kind][pos], r);
+                }
+            }
+        }
+        let mut next = heap.lossy;
+        while let Some(i) = next {
+            lossy -= 1;
+            next = heap.nodes[i].fix_next;
+        }
+        assert_eq!(lossy, 0);
+
+        // the queue holds every non-root
+        let mut queued = 0;
+        if let Some(front) = heap.queue {
+            let mut i = front;
+            loop {
+                assert_ne!(i, root);
+                assert_eq!(heap.nodes[heap.nodes[i].queue_next].queue_prev, i);
+                queued += 1;
+                i = heap.nodes[i].queue_next;
+                if i == front {
+                    break;
+                }
+            }
+        }
+        assert_eq!(queued, heap.len - 1);
+
+        let bound = 2 * heap.len.ilog2() as usize + 6;
+        let root_degree = std::iter::successors(heap.nodes[root].first, |&c| heap.nodes[c].next);
+        assert!(active_roots <= bound + 1, "{active_roots} active roots");
+        assert!(total_loss <= bound + 1, "{total_loss} total loss");
+        assert!(root_degree.count() <= bound + 3, "root degree");
+        assert!(max_degree <= bound, "degree {max_degree}");
+    }
+
+    /// Pops from the reference heap, skipping the elements which were
+    /// decreased since they were pushed.
+    fn pop_live(model: &mut v2::FibonacciHeap<Item>, live: &[Option<u32>]) -> Option<Item> {
+        std::iter::from_fn(|| model.pop()).find(|&(x, id)| live[id] == Some(x))
+    }
+
+    fn differential(ops: &[(u8, u32, usize)]) {
+        let mut heap = StrictFibonacciHeap::new();
+        let mut model = v2::FibonacciHeap::new();
+        // the current value of each id, while it is in the heap
+        let mut live: Vec<Option<u32>> = Vec::new();
+        let mut handles = Vec::new();
+
+        for &(op, x, i) in ops {
+            match op % 8 {
+                0 | 1 => {
+                    let popped = heap.pop();
+                    assert_eq!(popped, pop_live(&mut model, &live));
+                    if let Some((_, id)) = popped {
+                        live[id] = None;
+                        if let Some(h) = handles[id] {
+                            assert_eq!(heap.get(h), None);
+                        }
+                    }
+                }
+                2 | 3 if !live.is_empty() => {
+                    let id = i % live.len();
+                    if let (Some(v), Some(h)) = (live[id], handles[id]) {
+                        let v = v.min(x);
+                        heap.decrease_key(h, (v, id));
+                        model.push((v, id));
+                        live[id] = Some(v);
+                    }
+                }
+                4 => {
+                    // meld in a few more, from either side
+                    let mut other = StrictFibonacciHeap::new();
+                    let mut ids = Vec::new();
+                    for id in live.len()..=live.len() + i % 3 {
+                        ids.push(other.push((x, id)));
+                        model.push((x, id));
+                        live.push(Some(x));
+                    }
+
+                    // handles into the heap melded in go stale, and the
+                    // rest stay good for the decreases to come
+                    if i % 2 == 0 {
+                        heap.meld(other);
+                        for h in ids {
+                            assert_eq!(heap.get(h), None);
+                            handles.push(None);
+                        }
+                    } else {
+                        other.meld(heap);
+                        heap = other;
+                        for h in handles.iter_mut().filter_map(Option::take) {
+                            assert_eq!(heap.get(h), None);
+                        }
+                        handles.extend(ids.into_iter().map(Some));
+                    }
+                }
+                _ => {
+                    let id = live.len();
+                    handles.push(Some(heap.push((x, id))));
+                    model.push((x, id));
+                    live.push(Some(x));
+                }
+            }
+
+            assert_eq!(heap.len(), live.iter().flatten().count());
+            verify(&heap);
+        }
+
+        while let Some(popped) = heap.pop() {
+            assert_eq!(Some(popped), pop_live(&mut model, &live));
+            live[popped.1] = None;
+        }
+        assert_eq!(pop_live(&mut model, &live), None);
+    }
+
+    #[quickcheck]
+    fn matches_v2(ops: Vec<(u8, u32, usize)>) {
+        differential(&ops);
+    }
+
+    #[quickcheck]
+    fn matches_v2_with_small_keys(ops: Vec<(u8, u8, usize)>) {
+        // many equal keys, and decreases which change nothing
+        let ops = ops
+            .into_iter()
+            .map(|(op, x, i)| (op, x as u32 % 8, i))
+            .collect::<Vec<_>>();
+        differential(&ops);
+    }
+
+    #[quickcheck]
+    fn meld_keeps_both(xs: Vec<u32>, ys: Vec<u32>) {
+        for swap in [false, true] {
+            let mut a = StrictFibonacciHeap::from_iter(xs.iter().copied());
+            let mut b = StrictFibonacciHeap::from_iter(ys.iter().copied());
+            // leave some active nodes on both sides
+            a.pop();
+            b.pop();
+            if swap {
+                std::mem::swap(&mut a, &mut b);
+            }
+            let g = a.push(7);
+            let h = b.push(u32::MAX);
+            a.meld(b);
+            verify(&a);
+
+            // whichever heap is larger, handles into self stay good
+            assert_eq!(a.get(g), Some(&7));
+            assert_eq!(a.get(h), None);
+            a.decrease_key(g, 0);
+            verify(&a);
+
+            let mut exp = vec![0, u32::MAX];
+            for mut v in [xs.clone(), ys.clone()] {
+                v.sort();
+                exp.extend(v.into_iter().skip(1));
+            }
+            exp.sort();
+            assert_eq!(std::iter::from_fn(|| a.pop()).collect::<Vec<_>>(), exp);
+        }
+    }
+
+    #[test]
+    fn long_run_stays_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(314);
+        let ops = (0..5_000)
+            .map(|_| (rng.gen(), rng.gen_range(0..1_000_000), rng.gen()))
+            .collect::<Vec<_>>();
+        differential(&ops);
+    }
+
+    #[test]
+    fn decreasing_everything_stays_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(314);
+        let mut heap = StrictFibonacciHeap::new();
+        let handles = (0..2_000u32)
+            .map(|i| heap.push(1_000_000 + i))
+            .collect::<Vec<_>>();
+        verify(&heap);
+
+        for (i, &h) in handles.iter().enumerate() {
+            heap.decrease_key(h, rng.gen_range(0..1_000_000));
+            if i % 100 == 0 {
+                heap.pop();
+            }
+            verify(&heap);
+        }
+
+        let mut last = 0;
+        while let Some(x) = heap.pop() {
+            assert!(last <= x);
+            last = x;
+            verify(&heap);
+        }
+    }
+}
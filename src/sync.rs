@@ -0,0 +1,311 @@
+//! A blocking, multi-producer priority channel.
+//!
+//! Rather than first-in first-out, the [`Receiver`] always receives the
+//! _minimum_ element sent so far. Disconnection mirrors [`std::sync::mpsc`]:
+//! receiving fails once every [`Sender`] has been dropped _and_ the channel is
+//! drained, sending fails once the [`Receiver`] has been dropped.
+use crate::v2::FibonacciHeap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+
+/// Create a new priority channel, returning the sender/receiver halves.
+pub fn priority_channel<T: Ord>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            heap: FibonacciHeap::new(),
+            senders: 1,
+            receiver: true,
+        }),
+        available: Condvar::new(),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    available: Condvar,
+}
+
+struct State<T> {
+    heap: FibonacciHeap<T>,
+    senders: usize,
+    receiver: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // the heap is never left half-modified, so poisoning can be ignored
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Ord> Sender<T> {
+    /// Send an element, failing if the receiver has been dropped.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(SendError(item));
+        }
+
+        state.heap.push(item);
+        drop(state);
+
+        self.shared.available.notify_one();
+        Ok(())
+    }
+
+    /// Send a batch of elements under a single lock, pushing each in `O(1)`.
+    ///
+    /// The batch is collected _outside_ of the lock, so the critical section
+    /// never runs the caller's iterator. On failure the batch is returned.
+    pub fn send_all<I>(&self, items: I) -> Result<(), SendError<Vec<T>>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let batch = items.into_iter().collect::<Vec<_>>();
+
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(SendError(batch));
+        }
+
+        let n = batch.len();
+        state.heap.extend(batch);
+        drop(state);
+
+        match n {
+            0 => (),
+            1 => self.shared.available.notify_one(),
+            _ => self.shared.available.notify_all(),
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        let last = state.senders == 0;
+        drop(state);
+
+        if last {
+            // wake a blocked receiver so it can observe the disconnect
+            self.shared.available.notify_all();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Ord> Receiver<T> {
+    /// Block until an element is available and receive the minimum one.
+    ///
+    /// Fails once all senders are dropped and the channel is empty.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(x) = state.heap.pop() {
+                return Ok(x);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self
+                .shared
+                .available
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Like [`recv`](Self::recv), but waits at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // an overflowing deadline is as good as waiting forever
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(x) => x,
+            None => return self.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(x) = state.heap.pop() {
+                return Ok(x);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+
+            state = self
+                .shared
+                .available
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Receive the minimum element without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.heap.pop() {
+            Some(x) => Ok(x),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// The number of elements waiting in the channel.
+    pub fn len(&self) -> usize {
+        self.shared.lock().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A blocking iterator over received elements, ending on disconnect.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use std::thread;
+
+    #[quickcheck]
+    fn receives_in_priority_order(xs: Vec<u32>) {
+        let (tx, rx) = priority_channel();
+        for x in &xs {
+            tx.send(*x).unwrap();
+        }
+        drop(tx);
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), xs);
+    }
+
+    #[quickcheck]
+    fn send_all_pushes_batches(xs: Vec<u32>, ys: Vec<u32>) {
+        let (tx, rx) = priority_channel();
+        assert!(tx.send_all(xs.iter().copied()).is_ok());
+        assert!(tx.send_all(ys.iter().copied()).is_ok());
+        drop(tx);
+
+        let mut exp = xs.into_iter().chain(ys).collect::<Vec<_>>();
+        exp.sort();
+        assert_eq!(rx.iter().collect::<Vec<_>>(), exp);
+    }
+
+    #[test]
+    fn concurrent_producers() {
+        let (tx, rx) = priority_channel();
+
+        let handles = (0..4u32)
+            .map(|t| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        tx.send(i * 4 + t).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+
+        let mut got = rx.iter().collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        got.sort();
+        assert_eq!(got, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn recv_blocks_until_send() {
+        let (tx, rx) = priority_channel();
+        let h = thread::spawn(move || rx.recv());
+        thread::sleep(Duration::from_millis(10));
+        tx.send(1).unwrap();
+        assert_eq!(h.join().unwrap(), Ok(1));
+    }
+
+    #[test]
+    fn disconnect_semantics() {
+        let (tx, rx) = priority_channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(5)),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        let tx2 = tx.clone();
+        tx.send(2).unwrap();
+        drop(tx);
+        tx2.send(1).unwrap();
+        drop(tx2);
+
+        // buffered elements are still received after disconnect
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.recv(), Err(RecvError));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(5)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+
+        let (tx, rx) = priority_channel();
+        drop(rx);
+        assert_eq!(tx.send(3), Err(SendError(3)));
+        assert_eq!(tx.send_all([1, 2]), Err(SendError(vec![1, 2])));
+    }
+
+    #[test]
+    fn dropping_last_sender_wakes_receiver() {
+        let (tx, rx) = priority_channel::<u32>();
+        let h = thread::spawn(move || rx.recv());
+        thread::sleep(Duration::from_millis(10));
+        drop(tx);
+        assert_eq!(h.join().unwrap(), Err(RecvError));
+    }
+}
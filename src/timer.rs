@@ -0,0 +1,299 @@
+//! A deadline timer queue built on [`v2::FibonacciHeap`].
+//!
+//! Cancellation is _lazy_: cancelling a timer only forgets its payload, the heap
+//! entry is discarded once it surfaces at the top. Since most timeouts are
+//! cancelled before they fire, the heap is rebuilt whenever dead entries
+//! outnumber live ones so that memory stays proportional to the live timers.
+use crate::v2::FibonacciHeap;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A source of the current time.
+///
+/// [`SystemClock`] is used by default, tests can supply a mock clock.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// A [`Clock`] backed by [`Instant::now`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Identifies a timer inserted into a [`TimerQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(u64);
+
+// ordering is by deadline first, then by insertion order (ids are ascending)
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Entry {
+    deadline: Instant,
+    id: TimerId,
+}
+
+pub struct TimerQueue<T, C = SystemClock> {
+    heap: FibonacciHeap<Entry>,
+    items: HashMap<TimerId, T>,
+    next_id: u64,
+    clock: C,
+}
+
+impl<T> TimerQueue<T> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<T> Default for TimerQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C: Clock> TimerQueue<T, C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            heap: FibonacciHeap::new(),
+            items: HashMap::new(),
+            next_id: 0,
+            clock,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The number of live (not yet fired or cancelled) timers.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Schedule `item` to expire at `deadline`.
+    pub fn insert(&mut self, deadline: Instant, item: T) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+
+        self.heap.push(Entry { deadline, id });
+        self.items.insert(id, item);
+
+        id
+    }
+
+    /// Schedule `item` to expire `timeout` from the clock's current time.
+    pub fn insert_after(&mut self, timeout: Duration, item: T) -> TimerId {
+        let deadline = self.clock.now() + timeout;
+        self.insert(deadline, item)
+    }
+
+    /// Cancel a timer, returning its item if it had not yet fired.
+    pub fn cancel(&mut self, id: TimerId) -> Option<T> {
+        let item = self.items.remove(&id)?;
+
+        // keep the top of the heap live so `next_deadline` stays accurate
+        self.discard_cancelled();
+
+        // dead entries dominate, rebuild the heap with the live ones only
+        if self.heap.len() > 2 * self.items.len() + 16 {
+            self.compact();
+        }
+
+        Some(item)
+    }
+
+    /// The earliest deadline of any live timer.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.heap.peek().map(|e| e.deadline)
+    }
+
+    /// The time remaining until the next timer expires, as seen by the clock.
+    ///
+    /// Returns `Some(Duration::ZERO)` if a timer has already expired.
+    pub fn time_until_next(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.next_deadline()
+            .map(|d| d.saturating_duration_since(now))
+    }
+
+    /// Remove and yield every timer whose deadline is at or before `now`, in
+    /// deadline order.
+    ///
+    /// Timers are removed lazily as the iterator is advanced, dropping the
+    /// iterator early leaves the remaining expired timers in the queue.
+    pub fn poll_expired(&mut self, now: Instant) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || {
+            if self.next_deadline()? > now {
+                return None;
+            }
+
+            let Entry { id, .. } = self.heap.pop()?;
+            let item = self.items.remove(&id);
+            debug_assert!(item.is_some(), "top of heap should be a live timer");
+            self.discard_cancelled();
+            item
+        })
+    }
+
+    /// [`poll_expired`](Self::poll_expired) using the clock's current time.
+    pub fn poll(&mut self) -> impl Iterator<Item = T> + '_ {
+        let now = self.clock.now();
+        self.poll_expired(now)
+    }
+
+    fn discard_cancelled(&mut self) {
+        while let Some(e) = self.heap.peek() {
+            if self.items.contains_key(&e.id) {
+                break;
+            }
+            self.heap.pop();
+        }
+    }
+
+    fn compact(&mut self) {
+        let old = std::mem::take(&mut self.heap);
+        self.heap = drain(old)
+            .filter(|e| self.items.contains_key(&e.id))
+            .collect();
+    }
+}
+
+fn drain<T: Ord>(mut heap: FibonacciHeap<T>) -> impl Iterator<Item = T> {
+    std::iter::from_fn(move || heap.pop())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Clone)]
+    struct MockClock(Rc<Cell<Instant>>);
+
+    impl MockClock {
+        fn new() -> Self {
+            Self(Rc::new(Cell::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            self.0.set(self.0.get() + by);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    fn ms(x: u64) -> Duration {
+        Duration::from_millis(x)
+    }
+
+    #[test]
+    fn fires_in_deadline_order() {
+        let clock = MockClock::new();
+        let mut q = TimerQueue::with_clock(clock.clone());
+
+        q.insert_after(ms(30), 'c');
+        q.insert_after(ms(10), 'a');
+        q.insert_after(ms(20), 'b');
+        q.insert_after(ms(40), 'd');
+
+        assert_eq!(q.time_until_next(), Some(ms(10)));
+        assert_eq!(q.poll().count(), 0);
+
+        clock.advance(ms(25));
+        assert_eq!(q.poll().collect::<String>(), "ab");
+        assert_eq!(q.time_until_next(), Some(ms(5)));
+
+        clock.advance(ms(100));
+        assert_eq!(q.poll().collect::<String>(), "cd");
+        assert!(q.is_empty());
+        assert_eq!(q.next_deadline(), None);
+    }
+
+    #[test]
+    fn cancelled_timers_do_not_fire() {
+        let clock = MockClock::new();
+        let mut q = TimerQueue::with_clock(clock.clone());
+
+        let a = q.insert_after(ms(10), 'a');
+        let b = q.insert_after(ms(20), 'b');
+        q.insert_after(ms(30), 'c');
+
+        assert_eq!(q.cancel(a), Some('a'));
+        assert_eq!(q.cancel(a), None);
+        assert_eq!(q.time_until_next(), Some(ms(20)));
+
+        clock.advance(ms(20));
+        assert_eq!(q.poll().collect::<String>(), "b");
+        // already fired
+        assert_eq!(q.cancel(b), None);
+
+        clock.advance(ms(20));
+        assert_eq!(q.poll().collect::<String>(), "c");
+    }
+
+    #[test]
+    fn mostly_cancelled_compacts() {
+        let clock = MockClock::new();
+        let mut q = TimerQueue::with_clock(clock);
+
+        let ids = (0..1000u64)
+            .map(|i| q.insert_after(ms(1000 - i), i))
+            .collect::<Vec<_>>();
+
+        // cancel all but the earliest, leaving the top of heap live
+        for id in &ids[..999] {
+            q.cancel(*id);
+        }
+
+        assert_eq!(q.len(), 1);
+        assert!(q.heap.len() <= 2 * q.len() + 16);
+    }
+
+    #[quickcheck]
+    fn matches_sorted_live_timers(xs: Vec<(u16, bool)>) {
+        let start = Instant::now();
+        let mut q = TimerQueue::new();
+
+        let mut live = Vec::new();
+        for (i, (x, cancel)) in xs.into_iter().enumerate() {
+            let deadline = start + ms(x as u64);
+            let id = q.insert(deadline, i);
+            if cancel {
+                assert_eq!(q.cancel(id), Some(i));
+            } else {
+                live.push((deadline, i));
+            }
+        }
+
+        live.sort();
+
+        assert_eq!(q.len(), live.len());
+        assert_eq!(q.next_deadline(), live.first().map(|x| x.0));
+
+        let half = start + ms(u16::MAX as u64 / 2);
+        let fired = q.poll_expired(half).collect::<Vec<_>>();
+        let split = live.partition_point(|x| x.0 <= half);
+        let exp = live.iter().map(|x| x.1).collect::<Vec<_>>();
+        assert_eq!(fired, exp[..split]);
+
+        let fired = q
+            .poll_expired(start + ms(u16::MAX as u64))
+            .collect::<Vec<_>>();
+        assert_eq!(fired, exp[split..]);
+        assert!(q.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//! Recording heap operations, for replaying real workloads.
+//!
+//! A trace is a header followed by a stream of records, with all integers
+//! little-endian and elements encoded with [`Element`]:
+//!
+//! | field   | encoding                                              |
+//! |---------|-------------------------------------------------------|
+//! | magic   | `b"FIBTRACE"`                                         |
+//! | version | `u16`, currently [`VERSION`]                          |
+//! | records | `u8` tag, then `1` push: element, `2` pop: nothing,   |
+//! |         | `3` meld: `u64` count then the elements               |
+//!
+//! The trace simply ends at the end of a record, so a recording which was cut
+//! short is still readable up to its last whole record.
+//!
+//! The `fibheap-replay` binary replays a trace of `u64` against each heap.
+use crate::snapshot::{invalid, Element};
+use std::io::{self, ErrorKind, Read, Write};
+use std::marker::PhantomData;
+
+pub const MAGIC: [u8; 8] = *b"FIBTRACE";
+pub const VERSION: u16 = 1;
+
+const PUSH: u8 = 1;
+const POP: u8 = 2;
+const MELD: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<T> {
+    Push(T),
+    Pop,
+    /// Meld in another heap holding these elements.
+    Meld(Vec<T>),
+}
+
+pub struct TraceWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut w: W) -> io::Result<Self> {
+        w.write_all(&MAGIC)?;
+        VERSION.encode(&mut w)?;
+        Ok(Self { w })
+    }
+
+    pub fn record<T: Element>(&mut self, op: &Op<T>) -> io::Result<()> {
+        match op {
+            Op::Push(x) => {
+                PUSH.encode(&mut self.w)?;
+                x.encode(&mut self.w)
+            }
+            Op::Pop => POP.encode(&mut self.w),
+            Op::Meld(xs) => {
+                MELD.encode(&mut self.w)?;
+                (xs.len() as u64).encode(&mut self.w)?;
+                xs.iter().try_for_each(|x| x.encode(&mut self.w))
+            }
+        }
+    }
+
+    pub fn push<T: Element>(&mut self, item: &T) -> io::Result<()> {
+        PUSH.encode(&mut self.w)?;
+        item.encode(&mut self.w)
+    }
+
+    pub fn pop(&mut self) -> io::Result<()> {
+        POP.encode(&mut self.w)
+    }
+
+    /// Flushes the trace, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// Reads the operations of a trace, see the [module docs](self).
+pub struct TraceReader<T, R> {
+    r: R,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: Element, R: Read> TraceReader<T, R> {
+    pub fn new(mut r: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid("not a heap trace"));
+        }
+
+        if u16::decode(&mut r)? != VERSION {
+            return Err(invalid("unsupported trace version"));
+        }
+
+        Ok(Self {
+            r,
+            _item: PhantomData,
+        })
+    }
+
+    fn next_op(&mut self) -> io::Result<Option<Op<T>>> {
+        // the end of the stream is only clean at a record boundary
+        let mut tag = [0];
+        if self.r.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+
+        let op = match tag[0] {
+            PUSH => Op::Push(T::decode(&mut self.r)?),
+            POP => Op::Pop,
+            MELD => {
+                let n = u64::decode(&mut self.r)?;
+                // don't trust n for the allocation, it may be corrupt
+                let mut xs = Vec::new();
+                for _ in 0..n {
+                    xs.push(T::decode(&mut self.r)?);
+                }
+                Op::Meld(xs)
+            }
+            _ => return Err(invalid("unknown trace record")),
+        };
+
+        Ok(Some(op))
+    }
+}
+
+impl<T: Element, R: Read> Iterator for TraceReader<T, R> {
+    type Item = io::Result<Op<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_op() {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                res => return res.transpose(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::Arbitrary;
+    use quickcheck_macros::*;
+
+    impl<T: Arbitrary> Arbitrary for Op<T> {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            match u8::arbitrary(g) % 8 {
+                0 => Op::Meld(Vec::arbitrary(g)),
+                1..=3 => Op::Pop,
+                _ => Op::Push(T::arbitrary(g)),
+            }
+        }
+    }
+
+    fn write(ops: &[Op<u64>]) -> Vec<u8> {
+        let mut w = TraceWriter::new(Vec::new()).unwrap();
+        for op in ops {
+            w.record(op).unwrap();
+        }
+        w.finish().unwrap()
+    }
+
+    fn read(buf: &[u8]) -> io::Result<Vec<Op<u64>>> {
+        TraceReader::new(buf)?.collect()
+    }
+
+    #[quickcheck]
+    fn round_trip(ops: Vec<Op<u64>>) {
+        assert_eq!(read(&write(&ops)).unwrap(), ops);
+    }
+
+    #[test]
+    fn convenience_methods_match_record() {
+        let mut w = TraceWriter::new(Vec::new()).unwrap();
+        w.push(&7u64).unwrap();
+        w.pop().unwrap();
+        let buf = w.finish().unwrap();
+
+        assert_eq!(buf, write(&[Op::Push(7), Op::Pop]));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let buf = write(&[Op::Push(1), Op::Meld(vec![2, 3]), Op::Pop]);
+        assert!(read(&buf).is_ok());
+
+        // every truncation inside a record fails, only record boundaries pass
+        let boundaries = [10, 19, 44];
+        for n in 0..buf.len() {
+            assert_eq!(read(&buf[..n]).is_ok(), boundaries.contains(&n), "{n}");
+        }
+
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        assert_eq!(read(&bad).err().unwrap().kind(), ErrorKind::InvalidData);
+
+        let mut bad = buf.clone();
+        bad[8] = 9; // version
+        assert_eq!(read(&bad).err().unwrap().kind(), ErrorKind::InvalidData);
+
+        let mut bad = buf;
+        bad[10] = 4; // tag
+        assert_eq!(read(&bad).err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
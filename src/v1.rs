@@ -1,21 +1,33 @@
+use crate::dot;
+use crate::invariant::InvariantViolation;
+use crate::snapshot::{self, Element};
+use crate::stats::{Counters, HeapStats};
 use std::collections::LinkedList;
+use std::io::{self, Read, Write};
 
 pub struct FibonacciHeap<T> {
     roots: LinkedList<Tree<T>>,
+    counters: Counters,
 }
 
 impl<T: Ord> FibonacciHeap<T> {
     pub fn new() -> Self {
         Self {
             roots: Default::default(),
+            counters: Counters::default(),
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
     pub fn len(&self) -> usize {
         count_nodes(&self.roots)
     }
 
     pub fn push(&mut self, item: T) {
+        self.counters.compare(!self.roots.is_empty() as usize);
         if self.peek().map(|o| &item <= o).unwrap_or(true) {
             // item is lt or eq to min value, or list is empty
             // push to front, becoming **new min**
@@ -23,6 +35,8 @@ impl<T: Ord> FibonacciHeap<T> {
         } else {
             self.roots.push_back(Tree::new(item));
         }
+
+        self.debug_validate();
     }
 
     pub fn peek(&self) -> Option<&T> {
@@ -40,15 +54,22 @@ impl<T: Ord> FibonacciHeap<T> {
         self.roots.extend(children);
 
         // perform the grouping of like-degrees
-        rebalance(&mut self.roots);
+        rebalance(&mut self.roots, &mut self.counters);
 
         // find the minimum root value
-        bring_min_to_front(&mut self.roots);
+        bring_min_to_front(&mut self.roots, &mut self.counters);
 
+        self.debug_validate();
         Some(node)
     }
 }
 
+impl<T: Ord> Default for FibonacciHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Ord> FromIterator<T> for FibonacciHeap<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
         let mut heap = Self::new();
@@ -65,10 +86,282 @@ impl<T: Ord> Extend<T> for FibonacciHeap<T> {
     }
 }
 
+#[cfg(feature = "serde")]
+mod flat {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialises the elements only, in no particular order.
+    impl<T: Serialize> Serialize for FibonacciHeap<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut stack = self.roots.iter().collect::<Vec<_>>();
+            let nodes = std::iter::from_fn(move || {
+                let tree = stack.pop()?;
+                stack.extend(&tree.children);
+                Some(tree.root())
+            });
+
+            serializer.collect_seq(nodes)
+        }
+    }
+
+    /// Heapifies the deserialised elements.
+    impl<'de, T: Deserialize<'de> + Ord> Deserialize<'de> for FibonacciHeap<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Vec::deserialize(deserializer).map(Self::from_iter)
+        }
+    }
+}
+
+/// Serialisation that preserves the shape of the forest.
+///
+/// Use with `#[serde(with = "fibheap::v1::structural")]`. The roots, their
+/// children, and the position of the minimum root are kept exactly.
+/// Deserialising validates the heap order, the minimum, and that no tree has
+/// a degree too large for its size, rejecting malformed input with an error.
+#[cfg(feature = "serde")]
+pub mod structural {
+    use super::*;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    #[serde(rename = "FibonacciHeap")]
+    struct ForestRef<'a, T> {
+        roots: &'a LinkedList<Tree<T>>,
+        min: Option<usize>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "FibonacciHeap")]
+    struct Forest<T> {
+        roots: LinkedList<Tree<T>>,
+        min: Option<usize>,
+    }
+
+    pub fn serialize<T, S>(heap: &FibonacciHeap<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        ForestRef {
+            roots: &heap.roots,
+            // the minimum is always the front root
+            min: (!heap.roots.is_empty()).then_some(0),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<FibonacciHeap<T>, D::Error>
+    where
+        T: Deserialize<'de> + Ord,
+        D: Deserializer<'de>,
+    {
+        let Forest { mut roots, min } = Forest::deserialize(deserializer)?;
+
+        for tree in &roots {
+            check_tree(tree).map_err(D::Error::custom)?;
+        }
+
+        match min {
+            None if roots.is_empty() => (),
+            Some(idx) if idx < roots.len() => {
+                let min = roots.iter().nth(idx).map(Tree::root);
+                if roots.iter().any(|t| Some(t.root()) < min) {
+                    return Err(D::Error::custom("min position is not the minimum root"));
+                }
+                // rotate the min to the front
+                let mut split = roots.split_off(idx);
+                split.append(&mut roots);
+                roots = split;
+            }
+            _ => return Err(D::Error::custom("min position does not index the roots")),
+        }
+
+        Ok(FibonacciHeap {
+            roots,
+            counters: Counters::default(),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use quickcheck_macros::*;
+
+        fn to_json(heap: &FibonacciHeap<u32>) -> String {
+            let mut buf = Vec::new();
+            serialize(heap, &mut serde_json::Serializer::new(&mut buf)).unwrap();
+            String::from_utf8(buf).unwrap()
+        }
+
+        fn from_json(s: &str) -> serde_json::Result<FibonacciHeap<u32>> {
+            deserialize(&mut serde_json::Deserializer::from_str(s))
+        }
+
+        #[quickcheck]
+        fn flat_round_trip(xs: Vec<u32>) {
+            let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+            heap.pop();
+            let json = serde_json::to_string(&heap).unwrap();
+            let mut back: FibonacciHeap<u32> = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(back.len(), heap.len());
+            while let Some(x) = heap.pop() {
+                assert_eq!(back.pop(), Some(x));
+            }
+        }
+
+        #[quickcheck]
+        fn structural_round_trip(xs: Vec<u32>) {
+            let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+            heap.pop();
+            let json = to_json(&heap);
+            let mut back = from_json(&json).unwrap();
+
+            // same shape serialises to the same output
+            assert_eq!(to_json(&back), json);
+            assert_eq!(back.len(), heap.len());
+            while let Some(x) = heap.pop() {
+                assert_eq!(back.pop(), Some(x));
+            }
+        }
+
+        #[test]
+        fn structural_rejects_malformed() {
+            let ok = r#"{"roots":[{"node":1,"children":[{"node":2,"children":[]}]},{"node":3,"children":[]}],"min":0}"#;
+            let heap = from_json(ok).unwrap();
+            assert_eq!(heap.len(), 3);
+            assert_eq!(heap.peek(), Some(&1));
+
+            // min position rotated to the front
+            let ok = r#"{"roots":[{"node":3,"children":[]},{"node":1,"children":[]}],"min":1}"#;
+            assert_eq!(from_json(ok).unwrap().peek(), Some(&1));
+
+            // child less than parent
+            let bad = r#"{"roots":[{"node":3,"children":[{"node":2,"children":[]}]}],"min":0}"#;
+            assert!(from_json(bad).is_err());
+
+            // min is not the minimum
+            let bad = r#"{"roots":[{"node":3,"children":[]},{"node":1,"children":[]}],"min":0}"#;
+            assert!(from_json(bad).is_err());
+
+            // min out of bounds, or missing
+            let bad = r#"{"roots":[{"node":1,"children":[]}],"min":1}"#;
+            assert!(from_json(bad).is_err());
+            let bad = r#"{"roots":[{"node":1,"children":[]}],"min":null}"#;
+            assert!(from_json(bad).is_err());
+
+            // degree 2 with only 3 nodes
+            let bad = r#"{"roots":[{"node":1,"children":[{"node":2,"children":[]},{"node":3,"children":[]}]}],"min":0}"#;
+            assert!(from_json(bad).is_err());
+
+            let empty = from_json(r#"{"roots":[],"min":null}"#).unwrap();
+            assert!(empty.is_empty());
+        }
+    }
+}
+
+impl<T: Ord + Element> FibonacciHeap<T> {
+    /// Writes a binary snapshot of the heap, see [`crate::snapshot`].
+    pub fn write_snapshot<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let roots = self.roots.iter().collect::<Vec<_>>();
+        let len = self.len();
+        snapshot::write_forest(w, len, &roots, |t| (&t.node, &t.children))
+    }
+
+    /// Reads a heap from a binary snapshot, preserving its shape.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the snapshot is corrupt or
+    /// does not describe a valid heap.
+    pub fn read_snapshot<R: Read>(r: R) -> io::Result<Self> {
+        let (_, roots) = snapshot::read_forest(r, |node, children| Tree { node, children })?;
+
+        for tree in &roots {
+            check_tree(tree).map_err(snapshot::invalid)?;
+        }
+        if roots
+            .iter()
+            .any(|t| Some(t.root()) < roots.first().map(Tree::root))
+        {
+            return Err(snapshot::invalid("first root is not the minimum"));
+        }
+
+        Ok(Self {
+            roots: roots.into_iter().collect(),
+            counters: Counters::default(),
+        })
+    }
+}
+
+impl<T: Ord> FibonacciHeap<T> {
+    /// Checks the invariants of the heap, see [`crate::invariant`].
+    ///
+    /// The length is not cached, so cannot mismatch.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        for tree in &self.roots {
+            check_tree(tree)?;
+        }
+
+        // the minimum is always the first root
+        if let Some(min) = self.roots.front() {
+            if self.roots.iter().any(|t| t.root() < min.root()) {
+                return Err(InvariantViolation::MinNotMinimal);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renders the forest in Graphviz DOT, labelling each element with
+    /// `label`. The minimum is highlighted and every node shows its degree.
+    pub fn to_dot(&self, label: impl Fn(&T) -> String) -> String {
+        let roots = self.roots.iter().collect::<Vec<_>>();
+        // the minimum is always the first root
+        let min = (!roots.is_empty()).then_some(0);
+        dot::forest_to_dot(&roots, min, |t| (&t.node, &t.children), label)
+    }
+
+    /// Structural statistics of the forest, see [`crate::stats`].
+    pub fn stats(&self) -> HeapStats {
+        HeapStats::from_forest(&self.roots, |t| &t.children, self.counters.get())
+    }
+
+    #[inline]
+    fn debug_validate(&self) {
+        #[cfg(feature = "debug-invariants")]
+        if let Err(e) = self.validate() {
+            panic!("heap invariant violated: {e}");
+        }
+    }
+}
+
+/// Checks the heap order and degree bound of a tree, returning its size.
+fn check_tree<T: Ord>(tree: &Tree<T>) -> Result<usize, InvariantViolation> {
+    let mut size = 1;
+    for child in &tree.children {
+        if child.root() < tree.root() {
+            return Err(InvariantViolation::HeapOrder);
+        }
+        size += check_tree(child)?;
+    }
+
+    // linking only ever joins trees of equal degree, so a tree of
+    // degree `d` holds at least `2^d` nodes
+    if tree.degree() > size.ilog2() as usize {
+        return Err(InvariantViolation::DegreeTooLarge {
+            degree: tree.degree(),
+            size,
+        });
+    }
+
+    Ok(size)
+}
+
 /// Rebalances the list of roots such that no two roots share the same degree.
 /// The method employed uses a temporary array to order the trees by degrees.
 /// This has a worst case of `O(n)` but is _amortised_ as `O(log n)`.
-fn rebalance<T: Ord>(roots: &mut LinkedList<Tree<T>>) {
+fn rebalance<T: Ord>(roots: &mut LinkedList<Tree<T>>, counters: &mut Counters) {
     if roots.is_empty() {
         return;
     }
@@ -81,6 +374,7 @@ fn rebalance<T: Ord>(roots: &mut LinkedList<Tree<T>>) {
     // initialise temp array with log2 of length
     let mut buf: Vec<Option<Tree<T>>> =
         std::iter::repeat_with(|| None).take(cap as usize).collect();
+    counters.alloc();
 
     // iterate through the roots
     while let Some(mut tree) = roots.pop_front() {
@@ -115,6 +409,10 @@ fn rebalance<T: Ord>(roots: &mut LinkedList<Tree<T>>) {
                     tree_b
                 }
             };
+
+            // only reached once two trees were compared and linked
+            counters.compare(1);
+            counters.link();
         }
     }
 
@@ -126,7 +424,9 @@ fn count_nodes<T>(xs: &LinkedList<Tree<T>>) -> usize {
     xs.iter().map(Tree::count_nodes).sum()
 }
 
-fn bring_min_to_front<T: Ord>(roots: &mut LinkedList<Tree<T>>) {
+fn bring_min_to_front<T: Ord>(roots: &mut LinkedList<Tree<T>>, counters: &mut Counters) {
+    counters.compare(roots.len().saturating_sub(1));
+
     let min_index = roots
         .iter()
         .enumerate()
@@ -143,6 +443,7 @@ fn bring_min_to_front<T: Ord>(roots: &mut LinkedList<Tree<T>>) {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Tree<T> {
     node: T,
     children: Vec<Tree<T>>,
@@ -177,7 +478,7 @@ mod tests {
     #[quickcheck]
     fn min_heap_property(xs: Vec<u32>) {
         let mut ll = LinkedList::from_iter(xs.into_iter().map(Tree::new));
-        rebalance(&mut ll);
+        rebalance(&mut ll, &mut Counters::default());
 
         // verify that all degrees are unique
         // we can leverage the fact that degrees are in _ascending_ order
@@ -190,19 +491,19 @@ mod tests {
         }
 
         // check that a rebalance does not break it
-        rebalance(&mut ll);
+        rebalance(&mut ll, &mut Counters::default());
     }
 
     #[quickcheck]
     fn recycle_on_min(xs: Vec<u32>) {
         let min = xs.iter().min().copied();
         let mut ll = LinkedList::from_iter(xs.into_iter().map(Tree::new));
-        bring_min_to_front(&mut ll);
+        bring_min_to_front(&mut ll, &mut Counters::default());
 
         assert_eq!(min.as_ref(), ll.front().map(Tree::root));
 
-        rebalance(&mut ll);
-        bring_min_to_front(&mut ll);
+        rebalance(&mut ll, &mut Counters::default());
+        bring_min_to_front(&mut ll, &mut Counters::default());
         assert_eq!(min.as_ref(), ll.front().map(Tree::root));
     }
 
@@ -272,4 +573,112 @@ mod tests {
             verify_min_heap(child);
         }
     }
+
+    #[quickcheck]
+    fn validates_after_ops(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::new();
+        for (i, x) in xs.into_iter().enumerate() {
+            if i % 3 == 0 {
+                heap.pop();
+            } else {
+                heap.push(x);
+            }
+            assert_eq!(heap.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn validate_finds_violations() {
+        fn heap(roots: Vec<Tree<u32>>) -> FibonacciHeap<u32> {
+            FibonacciHeap {
+                roots: roots.into_iter().collect(),
+                counters: Counters::default(),
+            }
+        }
+
+        let leaf = Tree::new;
+        let tree = |node, children| Tree { node, children };
+
+        assert_eq!(heap(vec![]).validate(), Ok(()));
+
+        let bad = heap(vec![tree(3, vec![leaf(2)])]);
+        assert_eq!(bad.validate(), Err(InvariantViolation::HeapOrder));
+
+        let bad = heap(vec![tree(1, vec![leaf(2), leaf(3)])]);
+        assert_eq!(
+            bad.validate(),
+            Err(InvariantViolation::DegreeTooLarge { degree: 2, size: 3 })
+        );
+
+        let bad = heap(vec![leaf(2), leaf(1)]);
+        assert_eq!(bad.validate(), Err(InvariantViolation::MinNotMinimal));
+    }
+
+    #[test]
+    fn dot_output() {
+        let mut heap = FibonacciHeap::from_iter([5, 3, 8, 1, 9, 2]);
+        heap.pop();
+
+        let expected = r#"digraph FibonacciHeap {
+    node [shape=box];
+    n0 [label="2\ndegree 0", style=filled, fillcolor=lightgrey];
+    n1 [label="3\ndegree 2"];
+    n1 -> n2;
+    n1 -> n3;
+    n2 [label="5\ndegree 0"];
+    n3 [label="8\ndegree 1"];
+    n3 -> n4;
+    n4 [label="9\ndegree 0"];
+    { rank=same; n0; n1; }
+}
+"#;
+        assert_eq!(heap.to_dot(|x| x.to_string()), expected);
+
+        let empty = FibonacciHeap::<u32>::new();
+        assert_eq!(
+            empty.to_dot(|x| x.to_string()),
+            "digraph FibonacciHeap {\n    node [shape=box];\n}\n"
+        );
+    }
+
+    #[test]
+    fn stats_of_forest() {
+        let mut heap = FibonacciHeap::from_iter([5, 3, 8, 1, 9, 2]);
+        heap.pop();
+
+        let stats = heap.stats();
+        assert_eq!(stats.nodes, 5);
+        assert_eq!(stats.roots, 2);
+        assert_eq!(stats.max_degree, 2);
+        assert_eq!(stats.degrees, vec![3, 1, 1]);
+        assert_eq!(stats.heights, [1, 3]);
+        assert_eq!(stats.counters.is_some(), cfg!(feature = "stats"));
+    }
+
+    #[quickcheck]
+    fn stats_count_nodes(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+        heap.pop();
+
+        let stats = heap.stats();
+        assert_eq!(stats.nodes, heap.len());
+        assert_eq!(stats.degrees.iter().sum::<usize>(), stats.nodes);
+        // every node but a root is somebody's child
+        let children = stats.degrees.iter().enumerate().map(|(d, n)| d * n);
+        assert_eq!(children.sum::<usize>(), stats.nodes - stats.roots);
+    }
+
+    #[cfg(feature = "stats")]
+    #[quickcheck]
+    fn counters_track_links(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+        heap.pop();
+
+        // every link joins two trees into one
+        let ops = heap.stats().counters.unwrap();
+        let trees = xs.len().saturating_sub(1) as u64;
+        assert_eq!(ops.links, trees - heap.stats().roots as u64);
+        assert_eq!(ops.allocations, (xs.len() > 1) as u64);
+        assert!(ops.comparisons >= ops.links);
+    }
 }
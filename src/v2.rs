@@ -1,6 +1,17 @@
+use crate::dot;
+use crate::invariant::InvariantViolation;
+use crate::snapshot::{self, Element};
+use crate::stats::{Counters, HeapStats};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone)]
 pub struct FibonacciHeap<T> {
     roots: Vec<Tree<T>>,
     len: usize,
+    counters: Counters,
+    // the layers beneath any open checkpoints
+    txn: Option<Box<Txn<T>>>,
 }
 
 impl<T: Ord> FibonacciHeap<T> {
@@ -8,17 +19,24 @@ impl<T: Ord> FibonacciHeap<T> {
         Self {
             roots: Default::default(),
             len: 0,
+            counters: Counters::default(),
+            txn: None,
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn len(&self) -> usize {
-        self.len
+        self.len + self.txn.as_ref().map_or(0, |txn| txn.len)
     }
 
     pub fn push(&mut self, item: T) {
         // item is lt or eq to min value, or list is empty
         // push to **back**, becoming **new min**
-        let new_min = self.peek().map(|o| &item <= o).unwrap_or(true);
+        let new_min = self.roots.last().map(|o| &item <= o.root()).unwrap_or(true);
+        self.counters.compare(!self.roots.is_empty() as usize);
 
         self.roots.push(Tree::new(item));
 
@@ -29,13 +47,69 @@ impl<T: Ord> FibonacciHeap<T> {
         }
 
         self.len += 1;
+        self.debug_validate();
     }
 
     pub fn peek(&self) -> Option<&T> {
-        self.roots.last().map(Tree::root)
+        let top = self.roots.last().map(Tree::root);
+        match &self.txn {
+            Some(txn) => txn.min_below(top).map_or(top, |j| txn.layers[j].peek()),
+            None => top,
+        }
+    }
+
+    /// Moves all the elements of `other` into `self`, leaving `other` empty.
+    ///
+    /// The shorter of the two root lists is concatenated onto the longer
+    /// without any consolidation, so this costs `O(min(roots of self, roots
+    /// of other))`.
+    pub fn append(&mut self, other: &mut Self) {
+        // other's checkpoints are released, its elements all count as pushed
+        other.release();
+        if other.roots.is_empty() {
+            return;
+        }
+
+        self.len += other.len;
+        other.len = 0;
+
+        // splice the shorter list onto the longer, which then holds the
+        // roots of both, so either may be the one left in `self`
+        if other.roots.len() > self.roots.len() {
+            std::mem::swap(&mut self.roots, &mut other.roots);
+        }
+        let (Some(a), Some(b)) = (self.roots.last(), other.roots.last()) else {
+            self.debug_validate();
+            return;
+        };
+        let other_is_min = b.root() < a.root();
+        self.counters.compare(1);
+
+        if other_is_min {
+            // other's min is at its back, so it stays at the back
+            self.roots.append(&mut other.roots);
+        } else {
+            // keep our min at the back
+            let min = self.roots.pop().expect("not empty");
+            self.roots.append(&mut other.roots);
+            self.roots.push(min);
+        }
+
+        self.debug_validate();
     }
 
     pub fn pop(&mut self) -> Option<T> {
+        if let Some(txn) = &mut self.txn {
+            // the minimum is beneath a checkpoint, so journal a copy of it
+            if let Some(j) = txn.min_below(self.roots.last().map(Tree::root)) {
+                let x = txn.layers[j].pop().expect("not empty");
+                txn.len -= 1;
+                txn.journal.push((j, (txn.clone)(&x)));
+                self.debug_validate();
+                return Some(x);
+            }
+        }
+
         // take the last of the roots, since this is the _minimum_ value
         let Tree { node, children } = match self.roots.pop() {
             Some(x) => x,
@@ -49,15 +123,22 @@ impl<T: Ord> FibonacciHeap<T> {
         self.roots.extend(children);
 
         // perform the grouping of like-degrees
-        rebalance(&mut self.roots, self.len);
+        rebalance(&mut self.roots, self.len, &mut self.counters);
 
         // find the minimum root value
-        order_min(&mut self.roots);
+        order_min(&mut self.roots, &mut self.counters);
 
+        self.debug_validate();
         Some(node)
     }
 }
 
+impl<T: Ord> Default for FibonacciHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Ord> FromIterator<T> for FibonacciHeap<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
         let mut heap = Self::new();
@@ -79,10 +160,603 @@ impl<T: Ord> Extend<T> for FibonacciHeap<T> {
     }
 }
 
+/// A point a [`FibonacciHeap`] can be rolled back to, see
+/// [`FibonacciHeap::checkpoint`].
+#[derive(Debug)]
+#[must_use = "a checkpoint stays open until rolled back or committed"]
+pub struct Checkpoint {
+    id: u64,
+}
+
+impl<T: Ord + Clone> FibonacciHeap<T> {
+    /// Opens a checkpoint which the heap can later be rolled back to.
+    ///
+    /// This is `O(1)`, nothing is cloned up front. While checkpoints are open,
+    /// elements pushed since the latest are kept apart from those beneath it,
+    /// and popping an element from beneath journals a clone of it. Checkpoints
+    /// nest, and `peek` and `pop` cost an extra `O(open checkpoints)`.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        static ID: AtomicU64 = AtomicU64::new(0);
+        let id = ID.fetch_add(1, Ordering::Relaxed);
+
+        let layer = Self {
+            roots: std::mem::take(&mut self.roots),
+            len: std::mem::take(&mut self.len),
+            counters: Counters::default(),
+            txn: None,
+        };
+
+        let txn = self.txn.get_or_insert_with(|| {
+            Box::new(Txn {
+                layers: Vec::new(),
+                len: 0,
+                journal: Vec::new(),
+                marks: Vec::new(),
+                clone: T::clone,
+            })
+        });
+        txn.len += layer.len;
+        txn.layers.push(layer);
+        txn.marks.push((id, txn.journal.len()));
+
+        self.debug_validate();
+        Checkpoint { id }
+    }
+}
+
+impl<T: Ord> FibonacciHeap<T> {
+    /// Undoes every push and pop since `cp` was opened, closing it and any
+    /// checkpoints opened after it.
+    ///
+    /// # Panics
+    /// Panics if `cp` is not open on this heap.
+    pub fn rollback(&mut self, cp: Checkpoint) {
+        let i = self.position(&cp);
+        let mut txn = self.txn.take().expect("checkpoint is open");
+
+        let (_, mark) = txn.marks[i];
+        txn.marks.truncate(i);
+        let journal = txn.journal.split_off(mark);
+
+        // everything pushed since is dropped with the layers above
+        txn.layers.truncate(i + 1);
+        let top = txn.layers.pop().expect("layer beneath checkpoint");
+        self.roots = top.roots;
+        self.len = top.len;
+
+        // elements popped from layers which remain are put back
+        for (j, x) in journal {
+            if j < i {
+                txn.layers[j].push(x);
+            } else if j == i {
+                self.push(x);
+            }
+        }
+        txn.len = txn.layers.iter().map(|l| l.len).sum();
+
+        if !txn.marks.is_empty() {
+            self.txn = Some(txn);
+        }
+
+        self.debug_validate();
+    }
+
+    /// Keeps every change since `cp` was opened, closing it and any
+    /// checkpoints opened after it.
+    ///
+    /// # Panics
+    /// Panics if `cp` is not open on this heap.
+    pub fn commit(&mut self, cp: Checkpoint) {
+        let i = self.position(&cp);
+        let txn = self.txn.as_mut().expect("checkpoint is open");
+
+        // journaled pops from the merged layers can no longer be restored,
+        // as rolling back an earlier checkpoint drops those layers anyway
+        txn.marks.truncate(i);
+        let merged = txn.layers.split_off(i);
+        txn.len -= merged.iter().map(|l| l.len).sum::<usize>();
+        if txn.marks.is_empty() {
+            self.txn = None;
+        }
+
+        for mut layer in merged {
+            self.append(&mut layer);
+        }
+
+        self.debug_validate();
+    }
+
+    /// The number of open checkpoints.
+    pub fn checkpoints(&self) -> usize {
+        self.txn.as_ref().map_or(0, |txn| txn.marks.len())
+    }
+
+    fn position(&self, cp: &Checkpoint) -> usize {
+        self.txn
+            .as_ref()
+            .and_then(|txn| txn.marks.iter().position(|&(id, _)| id == cp.id))
+            .expect("checkpoint is not open on this heap")
+    }
+
+    /// Closes all checkpoints, keeping every change.
+    fn release(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            for mut layer in txn.layers {
+                self.append(&mut layer);
+            }
+        }
+    }
+
+    /// All the roots, including those beneath checkpoints, with the minimum
+    /// last.
+    fn forest(&self) -> Vec<&Tree<T>> {
+        let Some(txn) = &self.txn else {
+            return self.roots.iter().collect();
+        };
+
+        let mut roots = txn
+            .layers
+            .iter()
+            .flat_map(|l| &l.roots)
+            .chain(&self.roots)
+            .collect::<Vec<_>>();
+        let min = roots
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, t)| t.root())
+            .map(|(idx, _)| idx);
+        if let Some(idx) = min {
+            let lastidx = roots.len() - 1;
+            roots.swap(idx, lastidx);
+        }
+        roots
+    }
+}
+
+#[derive(Clone)]
+struct Txn<T> {
+    // layers[0] holds the elements from before the first checkpoint, and
+    // layers[i] those pushed since checkpoint i; the heap's own roots are the
+    // top layer
+    layers: Vec<FibonacciHeap<T>>,
+    len: usize,
+    // elements popped from beneath the top layer, with the layer they came from
+    journal: Vec<(usize, T)>,
+    // each open checkpoint, with the journal length when it was opened
+    marks: Vec<(u64, usize)>,
+    clone: fn(&T) -> T,
+}
+
+impl<T: Ord> Txn<T> {
+    /// The layer whose minimum is less than `top`, if any.
+    fn min_below(&self, top: Option<&T>) -> Option<usize> {
+        let (j, min) = self
+            .layers
+            .iter()
+            .enumerate()
+            .filter_map(|(j, l)| Some((j, l.peek()?)))
+            .min_by_key(|(_, x)| *x)?;
+
+        match top {
+            Some(x) if x <= min => None,
+            _ => Some(j),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+mod par {
+    use super::*;
+    use rayon::prelude::*;
+
+    impl<T: Ord + Send> FromParallelIterator<T> for FibonacciHeap<T> {
+        fn from_par_iter<I>(par_iter: I) -> Self
+        where
+            I: IntoParallelIterator<Item = T>,
+        {
+            // each worker builds a local heap, which are melded together
+            par_iter
+                .into_par_iter()
+                .fold(Self::new, |mut heap, x| {
+                    heap.push(x);
+                    heap
+                })
+                .reduce(Self::new, |mut a, mut b| {
+                    a.append(&mut b);
+                    a
+                })
+        }
+    }
+
+    impl<T: Ord + Send> ParallelExtend<T> for FibonacciHeap<T> {
+        fn par_extend<I>(&mut self, par_iter: I)
+        where
+            I: IntoParallelIterator<Item = T>,
+        {
+            let mut other = Self::from_par_iter(par_iter);
+            self.append(&mut other);
+        }
+    }
+
+    impl<T: Ord + Send> FibonacciHeap<T> {
+        /// Consumes the heap, returning the elements in ascending order.
+        ///
+        /// Rather than popping each element, the forest is flattened and the
+        /// elements sorted in parallel.
+        pub fn par_into_sorted_vec(mut self) -> Vec<T> {
+            self.release();
+            let mut xs = Vec::with_capacity(self.len);
+            let mut stack = self.roots;
+            while let Some(Tree { node, children }) = stack.pop() {
+                xs.push(node);
+                stack.extend(children);
+            }
+
+            xs.par_sort_unstable();
+            xs
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use quickcheck_macros::*;
+
+        #[quickcheck]
+        fn from_par_iter_pops_by_min(xs: Vec<u32>) {
+            let mut heap = xs.par_iter().copied().collect::<FibonacciHeap<_>>();
+            assert_eq!(heap.len(), xs.len());
+
+            let mut xs = xs;
+            xs.sort();
+            let got = std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>();
+            assert_eq!(got, xs);
+        }
+
+        #[quickcheck]
+        fn par_extend_then_sorted_vec(xs: Vec<u32>, ys: Vec<u32>) {
+            let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+            let popped = heap.pop();
+            heap.par_extend(ys.par_iter().copied());
+
+            let mut exp = xs.into_iter().chain(ys).collect::<Vec<_>>();
+            exp.sort();
+            if let Some(p) = popped {
+                exp.remove(exp.binary_search(&p).unwrap());
+            }
+
+            assert_eq!(heap.par_into_sorted_vec(), exp);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod flat {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialises the elements only, in no particular order.
+    impl<T: Serialize + Ord> Serialize for FibonacciHeap<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut stack = self.forest();
+            let nodes = std::iter::from_fn(move || {
+                let tree = stack.pop()?;
+                stack.extend(&tree.children);
+                Some(tree.root())
+            });
+
+            serializer.collect_seq(nodes)
+        }
+    }
+
+    /// Heapifies the deserialised elements.
+    impl<'de, T: Deserialize<'de> + Ord> Deserialize<'de> for FibonacciHeap<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Vec::deserialize(deserializer).map(Self::from_iter)
+        }
+    }
+}
+
+/// Serialisation that preserves the shape of the forest.
+///
+/// Use with `#[serde(with = "fibheap::v2::structural")]`. The roots, their
+/// children, and the position of the minimum root are kept exactly.
+/// Deserialising validates the heap order, the minimum, and that no tree has
+/// a degree too large for its size, rejecting malformed input with an error.
+#[cfg(feature = "serde")]
+pub mod structural {
+    use super::*;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    #[serde(rename = "FibonacciHeap")]
+    struct ForestRef<'a, T> {
+        roots: Vec<&'a Tree<T>>,
+        min: Option<usize>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "FibonacciHeap")]
+    struct Forest<T> {
+        roots: Vec<Tree<T>>,
+        min: Option<usize>,
+    }
+
+    pub fn serialize<T, S>(heap: &FibonacciHeap<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize + Ord,
+        S: Serializer,
+    {
+        let roots = heap.forest();
+        ForestRef {
+            // the minimum is always the last root
+            min: roots.len().checked_sub(1),
+            roots,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<FibonacciHeap<T>, D::Error>
+    where
+        T: Deserialize<'de> + Ord,
+        D: Deserializer<'de>,
+    {
+        let Forest { mut roots, min } = Forest::deserialize(deserializer)?;
+
+        let mut len = 0;
+        for tree in &roots {
+            len += check_tree(tree).map_err(D::Error::custom)?;
+        }
+
+        match min {
+            None if roots.is_empty() => (),
+            Some(idx) if idx < roots.len() => {
+                if roots.iter().any(|t| t.root() < roots[idx].root()) {
+                    return Err(D::Error::custom("min position is not the minimum root"));
+                }
+                let lastidx = roots.len() - 1;
+                roots.swap(idx, lastidx); // min at end
+            }
+            _ => return Err(D::Error::custom("min position does not index the roots")),
+        }
+
+        Ok(FibonacciHeap {
+            roots,
+            len,
+            counters: Counters::default(),
+            txn: None,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use quickcheck_macros::*;
+
+        fn to_json(heap: &FibonacciHeap<u32>) -> String {
+            let mut buf = Vec::new();
+            serialize(heap, &mut serde_json::Serializer::new(&mut buf)).unwrap();
+            String::from_utf8(buf).unwrap()
+        }
+
+        fn from_json(s: &str) -> serde_json::Result<FibonacciHeap<u32>> {
+            deserialize(&mut serde_json::Deserializer::from_str(s))
+        }
+
+        #[quickcheck]
+        fn flat_round_trip(xs: Vec<u32>) {
+            let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+            heap.pop();
+            let json = serde_json::to_string(&heap).unwrap();
+            let mut back: FibonacciHeap<u32> = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(back.len(), heap.len());
+            while let Some(x) = heap.pop() {
+                assert_eq!(back.pop(), Some(x));
+            }
+        }
+
+        #[quickcheck]
+        fn structural_round_trip(xs: Vec<u32>) {
+            let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+            heap.pop();
+            let json = to_json(&heap);
+            let mut back = from_json(&json).unwrap();
+
+            // same shape serialises to the same output
+            assert_eq!(to_json(&back), json);
+            assert_eq!(back.len(), heap.len());
+            while let Some(x) = heap.pop() {
+                assert_eq!(back.pop(), Some(x));
+            }
+        }
+
+        #[quickcheck]
+        fn serialises_beneath_checkpoints(xs: Vec<u32>, ys: Vec<u32>) {
+            let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+            let _cp = heap.checkpoint();
+            heap.extend(ys.iter().copied());
+
+            let mut back = from_json(&to_json(&heap)).unwrap();
+            assert_eq!(back.len(), heap.len());
+            while let Some(x) = heap.pop() {
+                assert_eq!(back.pop(), Some(x));
+            }
+        }
+
+        #[test]
+        fn structural_rejects_malformed() {
+            let ok = r#"{"roots":[{"node":3,"children":[]},{"node":1,"children":[{"node":2,"children":[]}]}],"min":1}"#;
+            let heap = from_json(ok).unwrap();
+            assert_eq!(heap.len(), 3);
+            assert_eq!(heap.peek(), Some(&1));
+
+            // min position swapped to the back
+            let ok = r#"{"roots":[{"node":1,"children":[]},{"node":3,"children":[]}],"min":0}"#;
+            assert_eq!(from_json(ok).unwrap().peek(), Some(&1));
+
+            // child less than parent
+            let bad = r#"{"roots":[{"node":3,"children":[{"node":2,"children":[]}]}],"min":0}"#;
+            assert!(from_json(bad).is_err());
+
+            // min is not the minimum
+            let bad = r#"{"roots":[{"node":1,"children":[]},{"node":3,"children":[]}],"min":1}"#;
+            assert!(from_json(bad).is_err());
+
+            // min out of bounds, or missing
+            let bad = r#"{"roots":[{"node":1,"children":[]}],"min":1}"#;
+            assert!(from_json(bad).is_err());
+            let bad = r#"{"roots":[{"node":1,"children":[]}],"min":null}"#;
+            assert!(from_json(bad).is_err());
+
+            // degree 2 with only 3 nodes
+            let bad = r#"{"roots":[{"node":1,"children":[{"node":2,"children":[]},{"node":3,"children":[]}]}],"min":0}"#;
+            assert!(from_json(bad).is_err());
+
+            let empty = from_json(r#"{"roots":[],"min":null}"#).unwrap();
+            assert!(empty.is_empty());
+        }
+    }
+}
+
+impl<T: Ord + Element> FibonacciHeap<T> {
+    /// Writes a binary snapshot of the heap, see [`crate::snapshot`].
+    pub fn write_snapshot<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        // the snapshot stores the minimum first
+        let mut roots = self.forest();
+        if !roots.is_empty() {
+            roots.rotate_right(1);
+        }
+        snapshot::write_forest(w, self.len(), &roots, |t| (&t.node, &t.children))
+    }
+
+    /// Reads a heap from a binary snapshot, preserving its shape.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the snapshot is corrupt or
+    /// does not describe a valid heap.
+    pub fn read_snapshot<R: Read>(r: R) -> io::Result<Self> {
+        let (len, mut roots) = snapshot::read_forest(r, |node, children| Tree { node, children })?;
+
+        for tree in &roots {
+            check_tree(tree).map_err(snapshot::invalid)?;
+        }
+        if roots
+            .iter()
+            .any(|t| Some(t.root()) < roots.first().map(Tree::root))
+        {
+            return Err(snapshot::invalid("first root is not the minimum"));
+        }
+
+        if !roots.is_empty() {
+            roots.rotate_left(1); // min at end
+        }
+
+        Ok(Self {
+            roots,
+            len,
+            counters: Counters::default(),
+            txn: None,
+        })
+    }
+}
+
+impl<T: Ord> FibonacciHeap<T> {
+    /// Checks the invariants of the heap, see [`crate::invariant`].
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let mut counted = 0;
+        for tree in &self.roots {
+            counted += check_tree(tree)?;
+        }
+        if counted != self.len {
+            return Err(InvariantViolation::LenMismatch {
+                cached: self.len,
+                counted,
+            });
+        }
+
+        // the minimum is always the last root
+        if let Some(min) = self.roots.last() {
+            if self.roots.iter().any(|t| t.root() < min.root()) {
+                return Err(InvariantViolation::MinNotMinimal);
+            }
+        }
+
+        // each layer beneath a checkpoint is a heap of its own
+        if let Some(txn) = &self.txn {
+            let mut counted = 0;
+            for layer in &txn.layers {
+                layer.validate()?;
+                counted += layer.len;
+            }
+            if counted != txn.len {
+                return Err(InvariantViolation::LenMismatch {
+                    cached: txn.len,
+                    counted,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renders the forest in Graphviz DOT, labelling each element with
+    /// `label`. The minimum is highlighted and every node shows its degree.
+    pub fn to_dot(&self, label: impl Fn(&T) -> String) -> String {
+        let roots = self.forest();
+        // the minimum is always the last root
+        let min = roots.len().checked_sub(1);
+        dot::forest_to_dot(&roots, min, |t| (&t.node, &t.children), label)
+    }
+
+    /// Structural statistics of the forest, including any layers beneath
+    /// checkpoints, see [`crate::stats`].
+    pub fn stats(&self) -> HeapStats {
+        let mut counters = self.counters.get();
+        if let (Some(c), Some(txn)) = (&mut counters, &self.txn) {
+            for layer in &txn.layers {
+                *c += layer.counters.get().unwrap_or_default();
+            }
+        }
+
+        HeapStats::from_forest(self.forest(), |t| &t.children, counters)
+    }
+
+    #[inline]
+    fn debug_validate(&self) {
+        #[cfg(feature = "debug-invariants")]
+        if let Err(e) = self.validate() {
+            panic!("heap invariant violated: {e}");
+        }
+    }
+}
+
+/// Checks the heap order and degree bound of a tree, returning its size.
+fn check_tree<T: Ord>(tree: &Tree<T>) -> Result<usize, InvariantViolation> {
+    let mut size = 1;
+    for child in &tree.children {
+        if child.root() < tree.root() {
+            return Err(InvariantViolation::HeapOrder);
+        }
+        size += check_tree(child)?;
+    }
+
+    // linking only ever joins trees of equal degree, so a tree of
+    // degree `d` holds at least `2^d` nodes
+    if tree.degree() > size.ilog2() as usize {
+        return Err(InvariantViolation::DegreeTooLarge {
+            degree: tree.degree(),
+            size,
+        });
+    }
+
+    Ok(size)
+}
+
 /// Rebalances the list of roots such that no two roots share the same degree.
 /// The method employed uses a temporary array to order the trees by degrees.
 /// This has a worst case of `O(n)` but is _amortised_ as `O(log n)`.
-fn rebalance<T: Ord>(roots: &mut Vec<Tree<T>>, nodes: usize) {
+pub(crate) fn rebalance<L: Link>(roots: &mut Vec<L>, nodes: usize, counters: &mut Counters) {
     if roots.is_empty() {
         return;
     }
@@ -91,8 +765,8 @@ fn rebalance<T: Ord>(roots: &mut Vec<Tree<T>>, nodes: usize) {
     let cap = nodes.ilog2() + 1;
 
     // initialise temp array with log2 of length
-    let mut buf: Vec<Option<Tree<T>>> =
-        std::iter::repeat_with(|| None).take(cap as usize).collect();
+    let mut buf: Vec<Option<L>> = std::iter::repeat_with(|| None).take(cap as usize).collect();
+    counters.alloc();
 
     // iterate through the roots
     while let Some(mut tree) = roots.pop() {
@@ -115,18 +789,16 @@ fn rebalance<T: Ord>(roots: &mut Vec<Tree<T>>, nodes: usize) {
                 // there was already a tree with the same degree
                 // and the new tree has a lesser root value
                 // make the old tree a child of the new one
-                Some(tree_b) if tree.root() <= tree_b.root() => {
-                    tree.children.push(tree_b);
-                    tree
-                }
+                Some(tree_b) if tree.root() <= tree_b.root() => tree.link(tree_b),
                 // there was already a tree with the same degree
                 // and the new tree has a greater root value
                 // make the new tree a child of the old one
-                Some(mut tree_b) => {
-                    tree_b.children.push(tree);
-                    tree_b
-                }
+                Some(tree_b) => tree_b.link(tree),
             };
+
+            // only reached once two trees were compared and linked
+            counters.compare(1);
+            counters.link();
         }
     }
 
@@ -134,7 +806,9 @@ fn rebalance<T: Ord>(roots: &mut Vec<Tree<T>>, nodes: usize) {
     roots.extend(buf.into_iter().filter_map(|x| x));
 }
 
-fn order_min<T: Ord>(roots: &mut [Tree<T>]) {
+pub(crate) fn order_min<L: Link>(roots: &mut [L], counters: &mut Counters) {
+    counters.compare(roots.len().saturating_sub(1));
+
     let min_index = roots
         .iter()
         .enumerate()
@@ -147,13 +821,15 @@ fn order_min<T: Ord>(roots: &mut [Tree<T>]) {
     }
 }
 
-struct Tree<T> {
-    node: T,
-    children: Vec<Tree<T>>,
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Tree<T> {
+    pub(crate) node: T,
+    pub(crate) children: Vec<Tree<T>>,
 }
 
 impl<T> Tree<T> {
-    fn new(root: T) -> Self {
+    pub(crate) fn new(root: T) -> Self {
         Self {
             node: root,
             children: Vec::new(),
@@ -169,6 +845,41 @@ impl<T> Tree<T> {
     }
 }
 
+/// A heap-ordered tree which can be linked under another tree.
+///
+/// This lets [`rebalance`] and [`order_min`] consolidate forests other than
+/// the one in [`FibonacciHeap`], such as the shared trees of
+/// [`persistent::FibHeap`](crate::persistent::FibHeap).
+pub(crate) trait Link {
+    type Item: Ord;
+
+    fn root(&self) -> &Self::Item;
+
+    fn degree(&self) -> usize;
+
+    /// Make `child` a child of `self`. Where it goes among the others is up
+    /// to the tree: v2 appends it last, the persistent heap conses it onto
+    /// the front of its shared list.
+    fn link(self, child: Self) -> Self;
+}
+
+impl<T: Ord> Link for Tree<T> {
+    type Item = T;
+
+    fn root(&self) -> &T {
+        &self.node
+    }
+
+    fn degree(&self) -> usize {
+        self.children.len()
+    }
+
+    fn link(mut self, child: Self) -> Self {
+        self.children.push(child);
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,7 +889,7 @@ mod tests {
     fn min_heap_property(xs: Vec<u32>) {
         let len = xs.len();
         let mut ll = Vec::from_iter(xs.into_iter().map(Tree::new));
-        rebalance(&mut ll, len);
+        rebalance(&mut ll, len, &mut Counters::default());
 
         // verify that all degrees are unique
         // we can leverage the fact that degrees are in _ascending_ order
@@ -191,7 +902,7 @@ mod tests {
         }
 
         // check that a rebalance does not break it
-        rebalance(&mut ll, len);
+        rebalance(&mut ll, len, &mut Counters::default());
     }
 
     #[quickcheck]
@@ -199,12 +910,12 @@ mod tests {
         let len = xs.len();
         let min = xs.iter().min().copied();
         let mut ll = Vec::from_iter(xs.into_iter().map(Tree::new));
-        order_min(&mut ll);
+        order_min(&mut ll, &mut Counters::default());
 
         assert_eq!(min.as_ref(), ll.last().map(Tree::root));
 
-        rebalance(&mut ll, len);
-        order_min(&mut ll);
+        rebalance(&mut ll, len, &mut Counters::default());
+        order_min(&mut ll, &mut Counters::default());
         assert_eq!(min.as_ref(), ll.last().map(Tree::root));
     }
 
@@ -251,6 +962,27 @@ mod tests {
         pops_by_min_check(vec![0, 0, 0, 1, 1]);
     }
 
+    #[quickcheck]
+    fn append_pops_by_min(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut a = FibonacciHeap::from_iter(xs.iter().copied());
+        let mut b = FibonacciHeap::from_iter(ys.iter().copied());
+        // pop once to get some non-trivial trees
+        let popped = a.pop();
+
+        a.append(&mut b);
+        assert!(b.is_empty());
+        assert_eq!(a.len(), xs.len() + ys.len() - popped.is_some() as usize);
+
+        let mut exp = xs.into_iter().chain(ys).collect::<Vec<_>>();
+        exp.sort();
+        if let Some(p) = popped {
+            exp.remove(exp.binary_search(&p).unwrap());
+        }
+
+        let got = std::iter::from_fn(|| a.pop()).collect::<Vec<_>>();
+        assert_eq!(got, exp);
+    }
+
     fn pops_by_min_check(mut xs: Vec<u32>) {
         let mut heap = FibonacciHeap::new();
 
@@ -274,4 +1006,226 @@ mod tests {
             verify_min_heap(child);
         }
     }
+
+    #[quickcheck]
+    fn rollback_restores_checkpoint(ops: Vec<(u8, u32)>) {
+        let mut heap = FibonacciHeap::new();
+        let mut model = Vec::new();
+        // each open checkpoint, with the model at that point
+        let mut cps: Vec<(Checkpoint, Vec<u32>)> = Vec::new();
+
+        for (op, x) in ops {
+            match op % 8 {
+                0 => cps.push((heap.checkpoint(), model.clone())),
+                1 if !cps.is_empty() => {
+                    let i = x as usize % cps.len();
+                    let (cp, m) = cps.drain(i..).next().unwrap();
+                    heap.rollback(cp);
+                    model = m;
+                }
+                2 if !cps.is_empty() => {
+                    let i = x as usize % cps.len();
+                    let (cp, _) = cps.drain(i..).next().unwrap();
+                    heap.commit(cp);
+                }
+                3 | 4 => {
+                    model.sort_unstable_by(|a, b| b.cmp(a));
+                    assert_eq!(heap.pop(), model.pop());
+                }
+                _ => {
+                    heap.push(x);
+                    model.push(x);
+                }
+            }
+
+            assert_eq!(heap.checkpoints(), cps.len());
+            assert_eq!(heap.len(), model.len());
+            assert_eq!(heap.peek(), model.iter().min());
+        }
+
+        model.sort();
+        assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), model);
+    }
+
+    #[quickcheck]
+    fn checkpoint_then_append(xs: Vec<u32>, ys: Vec<u32>) {
+        let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+        let cp = heap.checkpoint();
+        heap.pop();
+
+        let mut other = FibonacciHeap::from_iter(ys.iter().copied());
+        let _ = other.checkpoint();
+        heap.append(&mut other);
+        assert_eq!(heap.len(), xs.len().saturating_sub(1) + ys.len());
+
+        heap.rollback(cp);
+        assert_eq!(heap.checkpoints(), 0);
+
+        let mut xs = xs;
+        xs.sort();
+        assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), xs);
+    }
+
+    #[quickcheck]
+    fn clone_is_independent(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+        heap.pop();
+        let cp = heap.checkpoint();
+        heap.pop();
+
+        let mut copy = heap.clone();
+        copy.rollback(cp);
+        assert_eq!(copy.len(), xs.len().saturating_sub(1));
+        assert_eq!(heap.len(), xs.len().saturating_sub(2));
+        assert_eq!(heap.checkpoints(), 1);
+    }
+
+    #[test]
+    #[should_panic = "checkpoint is not open on this heap"]
+    fn rollback_closed_checkpoint() {
+        let mut heap = FibonacciHeap::new();
+        heap.push(1);
+        let outer = heap.checkpoint();
+        let inner = heap.checkpoint();
+        heap.rollback(outer);
+        heap.rollback(inner);
+    }
+
+    #[quickcheck]
+    fn validates_after_ops(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::new();
+        for (i, x) in xs.into_iter().enumerate() {
+            if i % 3 == 0 {
+                heap.pop();
+            } else {
+                heap.push(x);
+            }
+            assert_eq!(heap.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn validate_finds_violations() {
+        fn heap(roots: Vec<Tree<u32>>) -> FibonacciHeap<u32> {
+            FibonacciHeap {
+                len: roots.iter().map(count).sum(),
+                roots,
+                counters: Counters::default(),
+                txn: None,
+            }
+        }
+
+        fn count(t: &Tree<u32>) -> usize {
+            1 + t.children.iter().map(count).sum::<usize>()
+        }
+
+        let leaf = Tree::new;
+        let tree = |node, children| Tree { node, children };
+
+        assert_eq!(heap(vec![]).validate(), Ok(()));
+
+        let bad = heap(vec![tree(3, vec![leaf(2)])]);
+        assert_eq!(bad.validate(), Err(InvariantViolation::HeapOrder));
+
+        let bad = heap(vec![tree(1, vec![leaf(2), leaf(3)])]);
+        assert_eq!(
+            bad.validate(),
+            Err(InvariantViolation::DegreeTooLarge { degree: 2, size: 3 })
+        );
+
+        let bad = heap(vec![leaf(1), leaf(2)]);
+        assert_eq!(bad.validate(), Err(InvariantViolation::MinNotMinimal));
+
+        // cached length is off
+        let mut heap = FibonacciHeap::from_iter([1, 2, 3]);
+        heap.len += 1;
+        assert_eq!(
+            heap.validate(),
+            Err(InvariantViolation::LenMismatch {
+                cached: 4,
+                counted: 3
+            })
+        );
+    }
+
+    #[test]
+    fn dot_output() {
+        let mut heap = FibonacciHeap::from_iter([5, 3, 8, 1, 9, 2]);
+        heap.pop();
+
+        let expected = r#"digraph FibonacciHeap {
+    node [shape=box];
+    n0 [label="5\ndegree 0"];
+    n1 [label="2\ndegree 2", style=filled, fillcolor=lightgrey];
+    n1 -> n2;
+    n1 -> n3;
+    n2 [label="9\ndegree 0"];
+    n3 [label="3\ndegree 1"];
+    n3 -> n4;
+    n4 [label="8\ndegree 0"];
+    { rank=same; n0; n1; }
+}
+"#;
+        assert_eq!(heap.to_dot(|x| x.to_string()), expected);
+
+        let empty = FibonacciHeap::<u32>::new();
+        assert_eq!(
+            empty.to_dot(|x| x.to_string()),
+            "digraph FibonacciHeap {\n    node [shape=box];\n}\n"
+        );
+    }
+
+    #[test]
+    fn dot_escapes_labels() {
+        let heap = FibonacciHeap::from_iter(["say \"hi\"".to_string()]);
+        assert_eq!(
+            heap.to_dot(|x| x.clone()),
+            r#"digraph FibonacciHeap {
+    node [shape=box];
+    n0 [label="say \"hi\"\ndegree 0", style=filled, fillcolor=lightgrey];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn stats_of_forest() {
+        let mut heap = FibonacciHeap::from_iter([5, 3, 8, 1, 9, 2]);
+        heap.pop();
+
+        let stats = heap.stats();
+        assert_eq!(stats.nodes, 5);
+        assert_eq!(stats.roots, 2);
+        assert_eq!(stats.max_degree, 2);
+        assert_eq!(stats.degrees, vec![3, 1, 1]);
+        assert_eq!(stats.heights, [1, 3]);
+        assert_eq!(stats.counters.is_some(), cfg!(feature = "stats"));
+    }
+
+    #[quickcheck]
+    fn stats_count_nodes(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+        heap.pop();
+
+        let stats = heap.stats();
+        assert_eq!(stats.nodes, heap.len());
+        assert_eq!(stats.degrees.iter().sum::<usize>(), stats.nodes);
+        // every node but a root is somebody's child
+        let children = stats.degrees.iter().enumerate().map(|(d, n)| d * n);
+        assert_eq!(children.sum::<usize>(), stats.nodes - stats.roots);
+    }
+
+    #[cfg(feature = "stats")]
+    #[quickcheck]
+    fn counters_track_links(xs: Vec<u32>) {
+        let mut heap = FibonacciHeap::from_iter(xs.iter().copied());
+        heap.pop();
+
+        // every link joins two trees into one
+        let ops = heap.stats().counters.unwrap();
+        let trees = xs.len().saturating_sub(1) as u64;
+        assert_eq!(ops.links, trees - heap.stats().roots as u64);
+        assert_eq!(ops.allocations, (xs.len() > 1) as u64);
+        assert!(ops.comparisons >= ops.links);
+    }
 }